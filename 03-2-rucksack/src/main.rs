@@ -1,13 +1,129 @@
-// Calculates a "score" from letters duplicated between halves of a string.
+// Calculates a "score" from items shared between groups of lines and/or compartments within a line.
+// Defaults to groups of 3 whole lines; "--group 1 --compartments 2" scores halves of each line instead.
+// Items are any characters listed in the priority table; default table is a-z => 1..26, A-Z => 27..52.
 
 use std::io::{BufRead, BufReader, Error, ErrorKind, Stdin, stdin};
 use std::fs::File;
+use std::collections::HashMap;
 use either::Either;
-use std::collections::HashSet;
+use clap::Parser;
+
+#[derive(Parser)]
+struct Cli {
+	#[arg(short = 'g', long = "group", default_value_t = 3)]
+	group: usize,
+	#[arg(short = 'c', long = "compartments", default_value_t = 1)]
+	compartments: usize,
+	// File of priority entries, one per line; replaces the default table
+	#[arg(short = 't', long = "table")]
+	table: Option<String>,
+	// Extra priority entries, like "é=53" or "0-9=60"; applied after the table
+	#[arg(short = 'p', long = "priority")]
+	priority: Vec<String>,
+	filename: Option<String>
+}
+
+// Maps each item to a bit index (order of first appearance) and its priority
+#[derive(Default)]
+struct ItemTable {
+	index: HashMap<char, usize>,
+	items: Vec<(char, i64)>
+}
+impl ItemTable {
+	fn set(&mut self, item:char, priority:i64) {
+		match self.index.get(&item) {
+			Some(&idx) => self.items[idx].1 = priority,
+			None => {
+				self.index.insert(item, self.items.len());
+				self.items.push((item, priority));
+			}
+		}
+	}
+
+	// Entry is "X=N" for a single item or "X-Y=N" for a run of items scoring N, N+1, ...
+	fn add_entry(&mut self, entry:&str) -> Option<()> {
+		let (key, priority) = entry.rsplit_once('=')?;
+		let priority = priority.trim().parse::<i64>().ok()?;
+		let key:Vec<char> = key.trim().chars().collect();
+		match key[..] {
+			[item] => self.set(item, priority),
+			[from, '-', to] if from <= to => {
+				for (offset, item) in (from..=to).enumerate() {
+					self.set(item, priority + offset as i64)
+				}
+			},
+			_ => return None
+		}
+		Some(())
+	}
+
+	fn default_table() -> Self {
+		let mut table = ItemTable::default();
+		table.add_entry("a-z=1").unwrap();
+		table.add_entry("A-Z=27").unwrap();
+		table
+	}
+}
+
+// Bitset over item table indices. Default table fits in one word.
+#[derive(Clone)]
+struct ItemSet(Vec<u64>);
+impl ItemSet {
+	fn new(len:usize) -> Self { ItemSet(vec![0; (len+63)/64]) }
+	fn fill(&mut self, len:usize) {
+		for (word_idx, word) in self.0.iter_mut().enumerate() {
+			let bits = len.saturating_sub(word_idx*64).min(64);
+			*word = if bits == 64 { !0 } else { (1<<bits)-1 };
+		}
+	}
+	fn clear(&mut self) { self.0.fill(0) }
+	fn insert(&mut self, idx:usize) { self.0[idx/64] |= 1<<(idx%64) }
+	fn intersect(&mut self, other:&ItemSet) {
+		for (a, b) in self.0.iter_mut().zip(other.0.iter()) { *a &= b }
+	}
+	fn len(&self) -> u32 { self.0.iter().map(|x|x.count_ones()).sum() }
+	fn iter(&self) -> impl Iterator<Item=usize> + '_ {
+		self.0.iter().enumerate().flat_map(|(word_idx, &word)|
+			(0..64).filter(move |bit| word & (1<<bit) != 0).map(move |bit| word_idx*64 + bit))
+	}
+}
+
+fn lines_string(lines:&[usize]) -> String {
+	lines.iter().map(|x|x.to_string()).collect::<Vec<_>>().join(", ")
+}
 
 fn main() -> Result<(), Error> {
+	let (filename, group, compartments, table_filename, priority) = {
+		let cli = Cli::parse();
+		(cli.filename, cli.group, cli.compartments, cli.table, cli.priority)
+	};
+	if group == 0 || compartments == 0 {
+		return Err(Error::new(ErrorKind::InvalidInput, "Group size and compartment count must be at least 1"))
+	}
+
+	// Build priority table
+	let table = {
+		let invalid_entry = |s:&str| { Error::new(ErrorKind::InvalidInput, format!("Couldn't parse priority entry: '{}' (expecting X=N or X-Y=N)", s)) };
+		let mut table = match &table_filename {
+			None => ItemTable::default_table(),
+			Some(x) => {
+				let mut table = ItemTable::default();
+				for line in BufReader::new(std::fs::File::open(x)?).lines() {
+					let line = line?;
+					if line.trim().is_empty() { continue }
+					table.add_entry(&line).ok_or_else(||invalid_entry(&line))?;
+				}
+				table
+			}
+		};
+		for entry in &priority {
+			table.add_entry(entry).ok_or_else(||invalid_entry(entry))?;
+		}
+		table
+	};
+	let table_len = table.items.len();
+
 	// Load file from command-line argument or (if none) stdin
-	let filename = std::env::args().fuse().nth(1);
 	let input: Either<BufReader<Stdin>, BufReader<File>> = match &filename {
 		None => either::Left(BufReader::new(stdin())),
 		Some(x) => either::Right(BufReader::new(std::fs::File::open(x)?))
@@ -17,53 +133,58 @@ fn main() -> Result<(), Error> {
 
 	let mut total: i64 = 0;
 
-	// Will not enforce even criteria
-	let invalid = || { return Err(Error::new(ErrorKind::InvalidInput, "Expecting a string of ASCII letters")) };
+	let invalid = |line_num:usize, s:String| { Error::new(ErrorKind::InvalidInput, format!("Line {}: {}", line_num, s)) };
+	let items_string = |set:&ItemSet| -> String { set.iter().map(|idx|table.items[idx].0).collect() };
 
-	let mut last_set: HashSet<u8> = HashSet::with_capacity(26*2);
+	let mut common = ItemSet::new(table_len);
+	common.fill(table_len);
+	let mut set = ItemSet::new(table_len); // Scratch for one compartment
+	let mut group_lines: Vec<usize> = Vec::with_capacity(group); // 1-indexed line numbers of current group
 
 	// Scan file
 	for (line_idx, line) in lines.enumerate() {
 		let line = line?;
-		if line.is_empty() { continue; } // Assume blank lines are entry errors and skip
-		if !line.is_ascii() { return invalid() }
-		let mut this_set: HashSet<u8> = HashSet::with_capacity(26*2);
-		let first_set = line_idx%3 == 0;
-		let final_set = line_idx%3 == 2;
-		let letters = line.as_bytes();
-
-		// Construct this set
-		for letter in letters {
-			if first_set || last_set.contains(letter) {
-				this_set.insert(*letter);
-			}
+		let line_num = line_idx + 1;
+		if line.is_empty() { continue; } // Assume blank lines are entry errors and skip; they do not count toward a group
+		let items:Vec<char> = line.chars().collect();
+		if items.len() % compartments != 0 {
+			return Err(invalid(line_num, format!("Length {} does not split into {} equal compartments", items.len(), compartments)))
 		}
 
-		// Manage last set
-		if !final_set {
-			last_set = this_set;
-		} else {
-			if this_set.len() > 1 {
-				return Err(Error::new(ErrorKind::InvalidInput, "Found group with multiple duplicate letters"));
+		// Intersect each compartment into the group's common set
+		for compartment in items.chunks(items.len()/compartments) {
+			set.clear();
+			for item in compartment {
+				match table.index.get(item) {
+					Some(&idx) => set.insert(idx),
+					None => return Err(invalid(line_num, format!("No priority for item '{}'", item)))
+				}
 			}
-			let letter = match this_set.iter().next() {
-				None => return Err(Error::new(ErrorKind::InvalidInput, "Found group with no duplicate letters")),
-				Some(x) => *x
+			common.intersect(&set);
+		}
+		group_lines.push(line_num);
+
+		// Score finished group
+		if group_lines.len() == group {
+			let idx = match common.len() {
+				0 => return Err(Error::new(ErrorKind::InvalidInput,
+					format!("Found group with no common items (lines {})", lines_string(&group_lines)))),
+				1 => common.iter().next().unwrap(),
+				_ => return Err(Error::new(ErrorKind::InvalidInput,
+					format!("Found group with multiple common items \"{}\" (lines {})", items_string(&common), lines_string(&group_lines))))
 			};
-			//println!("Line {} common: {}", line_idx, letter as char);
-			let score;
-			if letter >= ('a' as u8) && letter <= ('z' as u8) {
-				score = letter-('a' as u8) + 1;
-			} else if letter >= ('A' as u8) && letter <= ('Z' as u8) {
-				score = letter-('A' as u8) + 1 + 26;
-			} else {
-				return invalid(); // Unnecessary
-			}
-			total += score as i64;
-			last_set.clear();
+			//println!("Lines {} common: {}", lines_string(&group_lines), table.items[idx].0);
+			total += table.items[idx].1;
+			common.fill(table_len);
+			group_lines.clear();
 		}
 	}
 
+	if !group_lines.is_empty() {
+		return Err(Error::new(ErrorKind::InvalidInput,
+			format!("Found incomplete group of {} (lines {})", group_lines.len(), lines_string(&group_lines))))
+	}
+
 	// Final score
 	println!("{}", total);
 
@@ -0,0 +1,152 @@
+// Closed integer intervals, sets of them, and a sweep-line overlap finder.
+
+#![allow(dead_code)] // Library; the day doesn't use every operation
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+// Inclusive on both ends, like the puzzle's "2-4"
+#[derive(Debug,Copy,Clone,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct Interval {
+	pub start:i64,
+	pub end:i64
+}
+
+impl Interval {
+	// None if inverted
+	pub fn new(start:i64, end:i64) -> Option<Interval> {
+		if end < start { None } else { Some(Interval {start, end}) }
+	}
+	pub fn len(&self) -> i64 { self.end - self.start + 1 }
+	pub fn contains(&self, other:&Interval) -> bool { self.start <= other.start && other.end <= self.end }
+	pub fn overlaps(&self, other:&Interval) -> bool { self.start <= other.end && other.start <= self.end }
+	pub fn intersection(&self, other:&Interval) -> Option<Interval> {
+		Interval::new(self.start.max(other.start), self.end.min(other.end))
+	}
+}
+
+impl std::fmt::Display for Interval {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{}-{}", self.start, self.end)
+	}
+}
+
+// Sorted, disjoint, non-adjacent intervals
+#[derive(Debug,Clone,Default,PartialEq,Eq)]
+pub struct IntervalSet(Vec<Interval>);
+
+impl IntervalSet {
+	// O(n log n)
+	pub fn from_intervals(intervals:impl IntoIterator<Item=Interval>) -> IntervalSet {
+		let mut v:Vec<Interval> = intervals.into_iter().collect();
+		v.sort_unstable();
+		IntervalSet(Self::merge_sorted(v))
+	}
+
+	// Input sorted by start; coalesces overlapping and adjacent intervals
+	fn merge_sorted(v:Vec<Interval>) -> Vec<Interval> {
+		let mut result:Vec<Interval> = Vec::with_capacity(v.len());
+		for i in v {
+			match result.last_mut() {
+				Some(last) if i.start <= last.end.saturating_add(1) => last.end = last.end.max(i.end),
+				_ => result.push(i)
+			}
+		}
+		result
+	}
+
+	pub fn intervals(&self) -> &[Interval] { &self.0 }
+	pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
+	// Total number of sections covered
+	pub fn len(&self) -> i64 { self.0.iter().map(Interval::len).sum() }
+
+	// Smallest single interval covering the whole set
+	pub fn hull(&self) -> Option<Interval> {
+		Some(Interval { start:self.0.first()?.start, end:self.0.last()?.end })
+	}
+
+	pub fn union(&self, other:&IntervalSet) -> IntervalSet {
+		let mut v:Vec<Interval> = Vec::with_capacity(self.0.len() + other.0.len());
+		let (mut a, mut b) = (self.0.iter().peekable(), other.0.iter().peekable());
+		loop { // Merge step of mergesort
+			let next = match (a.peek(), b.peek()) {
+				(Some(x), Some(y)) => if x.start <= y.start { a.next() } else { b.next() },
+				(Some(_), None) => a.next(),
+				(None, Some(_)) => b.next(),
+				(None, None) => break
+			};
+			v.push(*next.unwrap());
+		}
+		IntervalSet(Self::merge_sorted(v))
+	}
+
+	pub fn intersection(&self, other:&IntervalSet) -> IntervalSet {
+		let mut v:Vec<Interval> = Default::default();
+		let (mut i, mut j) = (0, 0);
+		while i < self.0.len() && j < other.0.len() {
+			let (x, y) = (&self.0[i], &other.0[j]);
+			if let Some(both) = x.intersection(y) { v.push(both) }
+			if x.end < y.end { i += 1 } else { j += 1 }
+		}
+		IntervalSet(v)
+	}
+
+	pub fn difference(&self, other:&IntervalSet) -> IntervalSet {
+		let mut v:Vec<Interval> = Default::default();
+		let mut j = 0;
+		for x in &self.0 {
+			// Skip subtrahends entirely before this interval
+			while j < other.0.len() && other.0[j].end < x.start { j += 1 }
+			let mut start = x.start;
+			let mut covered = false;
+			for y in other.0[j..].iter().take_while(|y| y.start <= x.end) {
+				if y.start > start { v.push(Interval { start, end:y.start-1 }) }
+				if y.end >= x.end { covered = true; break }
+				start = y.end + 1;
+			}
+			if !covered { v.push(Interval { start, end:x.end }) }
+		}
+		IntervalSet(v)
+	}
+
+	// O(log n)
+	pub fn contains(&self, interval:&Interval) -> bool {
+		let idx = self.0.partition_point(|x| x.end < interval.start);
+		self.0.get(idx).is_some_and(|x| x.contains(interval))
+	}
+}
+
+impl From<Interval> for IntervalSet {
+	fn from(i:Interval) -> Self { IntervalSet(vec![i]) }
+}
+
+impl std::fmt::Display for IntervalSet {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let s:Vec<String> = self.0.iter().map(|x|x.to_string()).collect();
+		write!(f, "{}", s.join(","))
+	}
+}
+
+// Every pair of indices (i < j) whose intervals overlap. O(n log n + pairs).
+// Sweeps by start, keeping a min-heap of active intervals keyed by end.
+pub fn overlapping_pairs(intervals:&[Interval]) -> Vec<(usize, usize)> {
+	let mut order:Vec<usize> = (0..intervals.len()).collect();
+	order.sort_unstable_by_key(|&i| intervals[i].start);
+
+	let mut active:BinaryHeap<Reverse<(i64, usize)>> = Default::default();
+	let mut pairs:Vec<(usize, usize)> = Default::default();
+	for idx in order {
+		let interval = &intervals[idx];
+		while let Some(&Reverse((end, _))) = active.peek() {
+			if end >= interval.start { break }
+			active.pop();
+		}
+		for &Reverse((_, other)) in active.iter() {
+			pairs.push((other.min(idx), other.max(idx)));
+		}
+		active.push(Reverse((interval.end, idx)));
+	}
+	pairs.sort_unstable();
+	pairs
+}
@@ -1,16 +1,46 @@
-// Given a list of ranges, determine how many overlap each other.
+// Given lines of comma-separated ranges, count lines where some pair of ranges overlap
+// (or, with --mode, where one contains another or all are disjoint).
+// With --redundant, reports ranges on each line fully covered by the others on that line.
+// With --analyze, also reports every overlapping pair of assignments across the whole file,
+// total coverage and unassigned sections.
 
 #![allow(unused_parens)]
 
+mod interval;
+
 use std::io::{BufRead, BufReader, Error, ErrorKind, Stdin, stdin};
 use std::fs::File;
 use either::Either;
+use clap::Parser as CliParser;
+use interval::{Interval, IntervalSet, overlapping_pairs};
 
 use pom::parser::*;
 
+#[derive(Debug,Copy,Clone,PartialEq,clap::ValueEnum)]
+enum Mode {
+	Contains, // Some range fully contains another
+	Overlaps, // Some pair of ranges overlap
+	Disjoint  // No pair of ranges overlap
+}
+
+#[derive(CliParser)]
+struct Cli {
+	#[arg(short = 'm', long = "mode", value_enum, default_value_t = Mode::Overlaps)]
+	mode: Mode,
+	#[arg(short = 'r', long = "redundant")]
+	redundant: bool,
+	#[arg(short = 'a', long = "analyze")]
+	analyze: bool,
+	filename: Option<String>
+}
+
 fn main() -> Result<(), Error> {
+	let (filename, mode, redundant, analyze) = {
+		let cli = Cli::parse();
+		(cli.filename, cli.mode, cli.redundant, cli.analyze)
+	};
+
     // Load file from command-line argument or (if none) stdin
-	let filename = std::env::args().fuse().nth(1);
 	let input: Either<BufReader<Stdin>, BufReader<File>> = match &filename {
 		None => either::Left(BufReader::new(stdin())),
 		Some(x) => either::Right(BufReader::new(std::fs::File::open(x)?))
@@ -20,33 +50,77 @@ fn main() -> Result<(), Error> {
 
 	let mut total: i64 = 0;
 
+	// Every assignment in the file, with the 1-indexed line and position it came from
+	let mut assignments: Vec<Interval> = Default::default();
+	let mut labels: Vec<(usize, usize)> = Default::default();
+
 	// p for positive
 	fn pinteger<'a>() -> Parser<'a, char, i64> {
-		let integer = one_of("123456789") - one_of("0123456789").repeat(0..) | sym('0');
+		let integer = (one_of("123456789") - one_of("0123456789").repeat(0..)) | sym('0');
 		integer.collect().convert(|s|String::from_iter(s.iter()).parse::<i64>())
 	}
-	fn range<'a>() -> Parser<'a, char, (i64, i64)>
+	type Range = (i64, i64);
+	fn range<'a>() -> Parser<'a, char, Range>
 		{ pinteger() - sym('-') + pinteger() }
-	fn range_pair<'a>() -> Parser<'a, char, ((i64, i64), (i64, i64))>
-		{ range() - sym(',') + range() }
+	fn range_list<'a>() -> Parser<'a, char, Vec<Range>>
+		{ list(range(), sym(',')) - end() }
 
-	let invalid = || { return Err(Error::new(ErrorKind::InvalidInput, "Expecting input with format [num]-[num],[num]-[num]")) };
+	let invalid = |line_num:usize, s:String| { Error::new(ErrorKind::InvalidInput, format!("Line {}: {}", line_num, s)) };
 
 	// Scan file
-	for line in lines {
+	for (line_idx, line) in lines.enumerate() {
 		let line = line?;
 		let line_array:Vec<char> = line.chars().collect();
-		let content = range_pair().parse(&line_array);
-		match content {
-			Ok(_t @ ((a,b),(c,d))) => {
-				if b<a || d<c { return invalid() }
-				let pass = (a..=b).contains(&c) || (a..=b).contains(&d)
-				        || (c..=d).contains(&a) || (c..=d).contains(&b);
-				//println!("{:?} {}", _t, pass);
-				if pass { total += 1 }
-			},
-			_ => return invalid()
+		let line_num = line_idx+1;
+		let content = match range_list().parse(&line_array) {
+			Ok(x) if !x.is_empty() => x,
+			_ => return Err(invalid(line_num, "Expecting input with format [num]-[num],[num]-[num],...".to_string()))
+		};
+		let mut ranges: Vec<Interval> = Vec::with_capacity(content.len());
+		for (a,b) in content {
+			match Interval::new(a,b) {
+				Some(x) => ranges.push(x),
+				None => return Err(invalid(line_num, format!("Range {}-{} is inverted", a, b)))
+			}
+		}
+
+		let pairs = || { (0..ranges.len()).flat_map(|i| (0..ranges.len()).filter(move |&j| j != i).map(move |j| (i,j))) };
+		let pass = match mode {
+			Mode::Contains => pairs().any(|(i,j)| ranges[i].contains(&ranges[j])),
+			Mode::Overlaps => pairs().any(|(i,j)| ranges[i].overlaps(&ranges[j])),
+			Mode::Disjoint => !pairs().any(|(i,j)| ranges[i].overlaps(&ranges[j]))
+		};
+		//println!("{:?} {}", ranges, pass);
+		if pass { total += 1 }
+
+		if redundant {
+			for (idx, interval) in ranges.iter().enumerate() {
+				let others = IntervalSet::from_intervals(ranges.iter().enumerate().filter(|&(j,_)| j != idx).map(|(_,x)| *x));
+				if others.contains(interval) {
+					println!("Redundant: line {} #{} ({}) covered by {}", line_num, idx+1, interval, others);
+				}
+			}
+		}
+
+		for (idx, interval) in ranges.into_iter().enumerate() {
+			assignments.push(interval);
+			labels.push((line_num, idx+1));
+		}
+	}
+
+	if analyze {
+		let label = |idx:usize| { format!("line {} #{} ({})", labels[idx].0, labels[idx].1, assignments[idx]) };
+		for (i, j) in overlapping_pairs(&assignments) {
+			println!("Overlap: {} and {}", label(i), label(j));
 		}
+		let coverage = IntervalSet::from_intervals(assignments.iter().copied());
+		let unassigned = match coverage.hull() {
+			Some(hull) => IntervalSet::from(hull).difference(&coverage),
+			None => IntervalSet::default()
+		};
+		println!("Coverage: {} sections ({})", coverage.len(), coverage);
+		println!("Unassigned: {} sections ({})", unassigned.len(), unassigned);
+		println!();
 	}
 
 	// Final score
@@ -1,94 +1,339 @@
 // Simulate a crane robot based on a drawing and a series of instructions.
+// With --render, prints the final state as a drawing followed by any unrun commands, which is valid input.
+// --model picks the crane: 9000 moves one crate at a time, 9001 (default) moves any stack at once,
+// lift:N moves up to N at once.
+// Crate labels may be several characters (like [AB12]); columns are wherever the footer numbers are.
+// With --play, steps through the commands interactively (needs a filename, since stdin is the controls).
 
-use std::io::{BufRead, BufReader, Error, ErrorKind, Stdin, stdin};
+use std::io::{BufRead, BufReader, Error, ErrorKind, Stdin, stdin, Write};
 use std::fs::File;
 use either::Either;
+use clap::Parser;
 
 use regex::Regex;
 
+const DEBUG:bool = false;
+
+// How many crates the crane can pick up at once; None for unlimited
+#[derive(Debug,Copy,Clone)]
+struct Model {
+	max_lift: Option<usize>
+}
+
+fn parse_model(s:&str) -> Result<Model, String> {
+	match s {
+		"9000" => Ok(Model { max_lift: Some(1) }),
+		"9001" => Ok(Model { max_lift: None }),
+		_ => match s.strip_prefix("lift:").map(|n| n.parse::<usize>()) {
+			Some(Ok(n)) if n > 0 => Ok(Model { max_lift: Some(n) }),
+			_ => Err("expected 9000, 9001 or lift:N with N at least 1".to_string())
+		}
+	}
+}
+
+#[derive(Parser)]
+struct Cli {
+	#[arg(short = 'm', long = "model", value_parser = parse_model, default_value = "9001")]
+	model: Model,
+	// Print final state as a drawing instead of the top crates
+	#[arg(short = 'r', long = "render")]
+	render: bool,
+	// Stop after this many commands
+	#[arg(short = 's', long = "stop")]
+	stop: Option<usize>,
+	// Interactive animated playback
+	#[arg(short = 'p', long = "play")]
+	play: bool,
+	// Milliseconds per animation frame in --play
+	#[arg(short = 'd', long = "delay", default_value_t = 150)]
+	delay: u64,
+	filename: Option<String>
+}
+
+// One "move x from y to z" line; stacks are 0-indexed here
+struct Move {
+	line_num: usize,
+	count: usize,
+	from: usize,
+	to: usize,
+	text: String
+}
+
+fn invalid(line_num:usize, s:String) -> Error {
+	Error::new(ErrorKind::InvalidInput, format!("Line {}: {}", line_num, s))
+}
+
+fn index_two<T>(a:& mut[T], b:usize, c:usize) -> (&mut T, &mut T) {
+	let ordered = b < c;
+	let (low_idx, high_idx) = if ordered { (b,c) } else { (c,b) };
+	let (low_slice, high_slice) = a.split_at_mut(high_idx);
+	let (low, high) = (&mut low_slice[low_idx], &mut high_slice[0]);
+	if ordered { (low, high) } else { (high, low) }
+}
+
+// Sizes of each lift the crane makes; each lift keeps its order
+fn lifts(count:usize, model:Model) -> impl Iterator<Item=usize> {
+	let lift = model.max_lift.unwrap_or(count).max(1);
+	(0..count).step_by(lift).map(move |done| lift.min(count-done))
+}
+
+fn check_move(data:&[Vec<String>], mv:&Move) -> Result<(), Error> {
+	if mv.count > data[mv.from].len() {
+		return Err(invalid(mv.line_num, format!("Can't move {} crates from stack {}, which holds {}", mv.count, mv.from+1, data[mv.from].len())))
+	}
+	Ok(())
+}
+
+// Returns the crates taken off the source stack, in their original order, for undo_move
+fn apply_move(data:&mut [Vec<String>], mv:&Move, model:Model) -> Result<Vec<String>, Error> {
+	check_move(data, mv)?;
+	let from_n = data[mv.from].len();
+	let taken = data[mv.from][from_n-mv.count..].to_vec();
+	if mv.from != mv.to {
+		let (column_from, column_to) = index_two(data, mv.from, mv.to);
+		for lift in lifts(mv.count, model) {
+			let column_from_n = column_from.len();
+			column_to.extend(column_from.drain(column_from_n-lift..));
+		}
+	}
+	Ok(taken)
+}
+
+fn undo_move(data:&mut [Vec<String>], mv:&Move, taken:Vec<String>) {
+	if mv.from != mv.to {
+		let to_n = data[mv.to].len();
+		data[mv.to].truncate(to_n-mv.count);
+		data[mv.from].extend(taken);
+	}
+}
+
+fn centered(s:&str, width:usize) -> String {
+	let pad = width - s.chars().count();
+	format!("{}{}{}", " ".repeat(pad/2), s, " ".repeat(pad - pad/2))
+}
+
+// Each column is as wide as its widest crate (at least 3)
+fn column_widths(data:&[Vec<String>]) -> Vec<usize> {
+	data.iter().enumerate().map(|(idx, column)|
+		column.iter().map(|tag| tag.chars().count()+2)
+			.chain([3, (idx+1).to_string().len()]).max().unwrap()
+	).collect()
+}
+
+// Inverse of the drawing parser: rows padded to full width, then numbered footer, no trailing newline
+// Crates and numbers are centered in their column
+fn render_drawing(data:&[Vec<String>]) -> String {
+	let widths = column_widths(data);
+	let height = data.iter().map(|column| column.len()).max().unwrap_or(0);
+	let mut rows: Vec<String> = Vec::with_capacity(height+1);
+	for level in (0..height).rev() {
+		rows.push(data.iter().zip(&widths).map(|(column, &width)| match column.get(level) {
+			Some(tag) => centered(&format!("[{}]", tag), width),
+			None => " ".repeat(width)
+		}).collect::<Vec<_>>().join(" "));
+	}
+	rows.push(widths.iter().enumerate().map(|(idx, &width)| centered(&(idx+1).to_string(), width)).collect::<Vec<_>>().join(" "));
+	rows.join("\n")
+}
+
+// Like render_drawing, for --play: the top "highlight" crates of a column are inverted,
+// and "hoist" crates hang in the air above a column
+fn draw_frame(data:&[Vec<String>], highlight:Option<(usize, usize)>, hoist:Option<(usize, &[String])>) -> String {
+	use ansi_term::Style;
+	use ansi_term::Colour::{Black, White};
+
+	let invert = Style::new().fg(Black).on(White);
+	let mut widths = column_widths(data);
+	if let Some((column, lifted)) = hoist {
+		for tag in lifted { widths[column] = widths[column].max(tag.chars().count()+2) }
+	}
+	let cell = |tag:&str, width:usize, lit:bool| {
+		let tag = format!("[{}]", tag);
+		let pad = width - tag.chars().count();
+		format!("{}{}{}", " ".repeat(pad/2), if lit { invert.paint(tag).to_string() } else { tag }, " ".repeat(pad - pad/2))
+	};
+
+	let mut rows: Vec<String> = Vec::new();
+	if let Some((hoist_column, lifted)) = hoist {
+		for tag in lifted.iter().rev() {
+			rows.push(widths.iter().enumerate().map(|(column, &width)|
+				if column == hoist_column { cell(tag, width, true) } else { " ".repeat(width) }
+			).collect::<Vec<_>>().join(" "));
+		}
+	}
+	rows.push(String::new());
+	let height = data.iter().map(|column| column.len()).max().unwrap_or(0);
+	for level in (0..height).rev() {
+		rows.push(data.iter().zip(&widths).enumerate().map(|(idx, (column, &width))| match column.get(level) {
+			Some(tag) => cell(tag, width, highlight.is_some_and(|(lit, count)| lit == idx && level + count >= column.len())),
+			None => " ".repeat(width)
+		}).collect::<Vec<_>>().join(" "));
+	}
+	rows.push(widths.iter().enumerate().map(|(idx, &width)| centered(&(idx+1).to_string(), width)).collect::<Vec<_>>().join(" "));
+	rows.join("\n")
+}
+
+// Interactive stepping through commands, with undo
+fn playback(mut data:Vec<Vec<String>>, commands:&[Move], model:Model, delay:u64) -> Result<(), Error> {
+	let mut history: Vec<Vec<String>> = Vec::new(); // Crates taken by each command run so far
+	let mut message = String::new();
+	let mut keys = stdin().lock().lines();
+
+	let show = |data:&[Vec<String>], done:usize, frame:String, message:&str| {
+		print!("\x1B[2J\x1B[1;1H");
+		println!("After command {} of {}", done, commands.len());
+		println!("{}", frame);
+		println!();
+		match commands.get(done) {
+			Some(mv) => println!("Next (line {}): {}", mv.line_num, mv.text),
+			None => println!("End. Top: {}", data.iter().map(|column| column.last().map_or("", |tag| tag.as_str())).collect::<Vec<_>>().join(","))
+		}
+		if !message.is_empty() { println!("{}", message) }
+		print!("[Enter] next, b back, g N jump to after command N, q quit > ");
+		std::io::stdout().flush()
+	};
+	let pause = || std::thread::sleep(std::time::Duration::from_millis(delay));
+
+	loop {
+		show(&data, history.len(), draw_frame(&data, None, None), &message)?;
+		message.clear();
+		let key = match keys.next() {
+			None => break,
+			Some(key) => key?
+		};
+		let mut words = key.split_whitespace();
+		match words.next() {
+			None | Some("n") => match commands.get(history.len()) {
+				None => message = "Already at end".to_string(),
+				Some(mv) => {
+					if let Err(e) = check_move(&data, mv) { message = e.to_string(); continue }
+					let done = history.len();
+					let taken = data[mv.from][data[mv.from].len()-mv.count..].to_vec();
+					if mv.from != mv.to {
+						// Animate each lift: pick up, carry, set down
+						for lift in lifts(mv.count, model) {
+							show(&data, done, draw_frame(&data, Some((mv.from, lift)), None), "")?; pause();
+							let from_n = data[mv.from].len();
+							let lifted = data[mv.from].split_off(from_n-lift);
+							show(&data, done, draw_frame(&data, None, Some((mv.from, &lifted))), "")?; pause();
+							show(&data, done, draw_frame(&data, None, Some((mv.to, &lifted))), "")?; pause();
+							data[mv.to].extend(lifted);
+							show(&data, done, draw_frame(&data, Some((mv.to, lift)), None), "")?; pause();
+						}
+					}
+					history.push(taken);
+				}
+			},
+			Some("b") => match history.pop() {
+				None => message = "Already at start".to_string(),
+				Some(taken) => undo_move(&mut data, &commands[history.len()], taken)
+			},
+			Some("g") => match words.next().and_then(|n| n.parse::<usize>().ok()) {
+				Some(target) if target <= commands.len() => {
+					while history.len() > target {
+						let taken = history.pop().unwrap();
+						undo_move(&mut data, &commands[history.len()], taken);
+					}
+					while history.len() < target {
+						match apply_move(&mut data, &commands[history.len()], model) {
+							Ok(taken) => history.push(taken),
+							Err(e) => { message = e.to_string(); break }
+						}
+					}
+				},
+				_ => message = format!("Expected g N with N from 0 to {}", commands.len())
+			},
+			Some("q") => break,
+			Some(x) => message = format!("Unknown command '{}'", x)
+		}
+	}
+	println!();
+	Ok(())
+}
+
 fn main() -> Result<(), Error> {
+	let (filename, model, render, stop, play, delay) = {
+		let cli = Cli::parse();
+		(cli.filename, cli.model, cli.render, cli.stop, cli.play, cli.delay)
+	};
+	if play && filename.is_none() {
+		return Err(Error::new(ErrorKind::InvalidInput, "--play needs a filename, since stdin is used for controls"))
+	}
+
     // Load file from command-line argument or (if none) stdin
-	let filename = std::env::args().fuse().nth(1);
 	let input: Either<BufReader<Stdin>, BufReader<File>> = match &filename {
 		None => either::Left(BufReader::new(stdin())),
 		Some(x) => either::Right(BufReader::new(std::fs::File::open(x)?))
 	};
 
-	let mut lines = input.lines();
+	let mut lines = input.lines().enumerate();
 
-	let invalid =   || { Err(Error::new(ErrorKind::InvalidInput, "Did not find expected ascii art diagram")) };
-	let invalide2 = || { Error::new(ErrorKind::InvalidInput, "Expected sentence like 'move x from y to z'") };
-	let invalid2 =  || { Err(invalide2()) };
-	let invalide3 = || { Error::new(ErrorKind::InvalidInput, "Out of crates") };
+	let invalid1 = |line_num:usize| { invalid(line_num, "Did not find expected ascii art diagram".to_string()) };
+	let invalid2 = |line_num:usize| { invalid(line_num, "Expected sentence like 'move x from y to z'".to_string()) };
 
-	// Series of either three spaces or [W], separated by spaces. Will capture W or S (for Word or Space)
-	let separator_re = Regex::new(r"^\p{gc:Zs}").unwrap();
-	let blank_re = Regex::new(r"^\p{gc:Zs}{3}").unwrap();
-	let crate_re = Regex::new(r"^\[(\w)\]").unwrap();
+	let crate_re = Regex::new(r"\[(\w+)\]").unwrap();
 	let numbers_re = Regex::new(r"^[\s\d]+$").unwrap();
+	let number_re = Regex::new(r"\d+").unwrap();
 	let move_re = Regex::new(r"^move (\d+) from (\d+) to (\d+)$").unwrap();
 
-	// Returns rest of string after match
-	fn match_next<'a>(m:regex::Captures, s:&'a str) -> &'a str {
-		return &s[m.get(0).unwrap().end()..]
+	// Regex matches give byte offsets, but columns line up by character
+	fn char_offset(s:&str, byte:usize) -> usize {
+		s[..byte].chars().count()
 	}
 
-	// Returns first match group, rest of string after match
-	fn match_next_get<'a, 'b>(m:regex::Captures<'a>, s:&'b str) -> (&'a str, &'b str) {
-		return (m.get(1).unwrap().as_str(), match_next(m, s))
-	}
-
-	fn index_two<T>(a:& mut[T], b:usize, c:usize) -> (&mut T, &mut T) {
-		let ordered = b < c;
-		let (low_idx, high_idx) = if ordered { (b,c) } else { (c,b) };
-		let (low_slice, high_slice) = a.split_at_mut(high_idx);
-		let (low, high) = (&mut low_slice[low_idx], &mut high_slice[0]);
-		return if ordered { (low, high) } else { (high, low) }
-	}
-
-	let mut data:Vec<Vec<char>> = Vec::new();
+	// Rows are kept until the footer says where the columns are
+	let mut rows: Vec<(usize, String)> = Vec::new();
+	let mut footer: Option<Vec<usize>> = None; // Character position of the middle of each column number
+	let mut line_num = 0;
 
 	// Scan file
-	for line in lines.by_ref() {
+	for (line_idx, line) in lines.by_ref() {
 		let line = line?;
-		let mut rest = line.as_str();
-		println!("Line");
-
-		// Note: Moves to phase 2 on first empty line, ignores number "comment"
-		// Does NOT check accuracy of number "comment"
-		if rest.is_empty() { break }
-		if let Some(_) = numbers_re.captures(rest) { continue }
-
-		let mut column = 0;
-		loop {
-			// Blank space before crate
-			if column > 0 {
-				if let Some(capture) = separator_re.captures(rest) {
-					rest = match_next(capture, rest)
-				} else {
-					break // End of string
+		line_num = line_idx + 1;
+		if DEBUG { println!("Line"); }
+
+		// Note: Moves to phase 2 on first empty (or all-whitespace) line
+		if line.trim().is_empty() { break }
+		if footer.is_some() { return Err(invalid(line_num, "Expected empty line after numbered footer".to_string())) }
+
+		// Footer must number the columns 1..n in order
+		if numbers_re.is_match(&line) {
+			let mut centers: Vec<usize> = Vec::new();
+			for (idx, number) in number_re.find_iter(&line).enumerate() {
+				if number.as_str().parse::<usize>().ok() != Some(idx+1) {
+					return Err(invalid(line_num, "Footer should number columns 1 to n in order".to_string()))
 				}
+				centers.push(char_offset(&line, number.start()) + (number.as_str().len()-1)/2);
 			}
-			// No crate
-			if let Some(capture) = blank_re.captures(rest) {
-				rest = match_next(capture, rest);
-			// Crate
-			} else if let Some(capture) = crate_re.captures(rest) {
-				let tag:&str;
-				(tag, rest) = match_next_get(capture, rest);
-				while data.len() <= column
-					{ data.push(Vec::new()) }
-				let tag_ch = tag.chars().next().unwrap();
-				data[column].push(tag_ch);
-				println!("Column {} tag {}", column, tag_ch);
-			} else {
-				return invalid();
-			}
-			column += 1
+			footer = Some(centers);
+			continue
+		}
+
+		rows.push((line_num, line));
+	}
+
+	if rows.is_empty() && footer.is_none() { return Err(invalid1(line_num.max(1))) }
+	let Some(footer) = footer else { return Err(invalid(line_num, "Drawing has no numbered footer".to_string())) };
+
+	// Each crate belongs to the column whose number is under it
+	let mut data:Vec<Vec<String>> = vec![Vec::new(); footer.len()];
+	for (line_num, row) in rows {
+		let mut last_end = 0;
+		for capture in crate_re.captures_iter(&row) {
+			let whole = capture.get(0).unwrap();
+			if !row[last_end..whole.start()].trim().is_empty() { return Err(invalid1(line_num)) }
+			last_end = whole.end();
+			let span = char_offset(&row, whole.start())..char_offset(&row, whole.end());
+			let column = footer.iter().position(|center| span.contains(center))
+				.ok_or_else(|| invalid(line_num, format!("Crate {} is not above a footer number", whole.as_str())))?;
+			let tag = capture.get(1).unwrap().as_str();
+			data[column].push(tag.to_string());
+			if DEBUG { println!("Column {} tag {}", column, tag); }
 		}
+		if !row[last_end..].trim().is_empty() { return Err(invalid1(line_num)) }
 	}
 
-	if data.len() == 0 { return invalid() }
+	if data.is_empty() { return Err(invalid1(line_num)) }
 
 	// Reverse all columns of data
 	// Note column not of same type as before
@@ -96,41 +341,58 @@ fn main() -> Result<(), Error> {
 		column.reverse()
 	}
 
-	for line in lines {
+	// Read all commands
+	let mut commands: Vec<Move> = Vec::new();
+	for (line_idx, line) in lines {
 		let line = line?;
-		println!("Command: {} On: {:?}", line, data);
+		let line_num = line_idx + 1;
 
 		if let Some(capture) = move_re.captures(&line) {
 			let v = capture.iter().skip(1)
 				.map(|x| match x {
-					None => Err(invalide2()),
-					Some(x) => x.as_str().parse::<usize>().map_err(|_|invalide2())
+					None => Err(invalid2(line_num)),
+					Some(x) => x.as_str().parse::<usize>().map_err(|_|invalid2(line_num))
 				}).collect::<Result<Vec<usize>, Error>>()?;
 
 			let [a,b,c] = <[usize; 3]>::try_from(v).ok().unwrap();
 
-			if b == 0 || c == 0 { return invalid2() }
-			if b != c {
-				let (column_from, column_to) = index_two(&mut data, b-1, c-1);
-
-				let column_from_n = column_from.len();
-				let column_from_post_n = column_from_n-a;
-				column_to.extend_from_slice(&column_from[column_from_post_n..column_from_n]);
-				column_from.truncate(column_from_post_n);
+			for column in [b, c] {
+				if column == 0 || column > data.len() {
+					return Err(invalid(line_num, format!("No stack {} (stacks are 1 to {})", column, data.len())))
+				}
 			}
+			commands.push(Move { line_num, count:a, from:b-1, to:c-1, text:line });
 		} else {
-			return invalid2()
+			return Err(invalid2(line_num))
 		}
 	}
 
-	// Debug, print entire tree
-	println!("Final: {:?}", data);
+	if play {
+		return playback(data, &commands, model, delay)
+	}
 
-	// Result code
-	for column in data {
-		print!("{}", column.last().unwrap_or(&' '));
+	let stop = stop.unwrap_or(commands.len()).min(commands.len());
+	for mv in &commands[..stop] {
+		if DEBUG { println!("Command: {} On: {:?}", mv.text, data); }
+		apply_move(&mut data, mv, model)?;
 	}
-	println!("");
+	let remaining = commands[stop..].iter().map(|mv| &mv.text); // Kept for --render
+
+	if render {
+		println!("{}", render_drawing(&data));
+		println!();
+		for line in remaining {
+			println!("{}", line);
+		}
+		return Ok(())
+	}
+
+	// Debug, print entire tree
+	if DEBUG { println!("Final: {:?}", data); }
+
+	// Result code: top label of each stack, comma separated (blank for empty stacks)
+	let tops: Vec<&str> = data.iter().map(|column| column.last().map_or("", |tag| tag.as_str())).collect();
+	println!("{}", tops.join(","));
 
 	Ok(())
 }
@@ -1,52 +1,120 @@
-// Find character where 4 unalike characters appear in a row
-// Does not support stdin
+// Find character where N unalike characters appear in a row, for each line (datastream) of input.
+// N is each --window given, default 4 (start-of-packet) and 14 (start-of-message).
+// With --all, prints every such position rather than just the first.
+// Reads incrementally, so markers are printed as soon as they arrive when reading a pipe.
 
-use std::io::{Error, ErrorKind};
+use std::io::{Error, ErrorKind, Stdin, stdin};
+use std::fs::File;
+use std::collections::{HashMap, VecDeque};
+use either::Either;
 use char_reader::CharReader;
+use clap::Parser;
 
-// Looking for 4 character sequence, so remember 3.
-const BACK:usize = 14;
+#[derive(Parser)]
+struct Cli {
+	#[arg(short = 'w', long = "window", default_values_t = [4, 14])]
+	window: Vec<usize>,
+	#[arg(short = 'a', long = "all")]
+	all: bool,
+	filename: Option<String>
+}
+
+// Sliding window over the last "window" characters. O(1) amortised per character:
+// keeps a count per symbol, plus how many characters in the window repeat an earlier one.
+struct Detector {
+	window: usize,
+	back: VecDeque<char>,
+	counts: HashMap<char, usize>,
+	duplicates: usize
+}
+
+impl Detector {
+	fn new(window:usize) -> Self {
+		Detector { window, back: VecDeque::with_capacity(window+1), counts: Default::default(), duplicates: 0 }
+	}
+
+	fn clear(&mut self) {
+		self.back.clear();
+		self.counts.clear();
+		self.duplicates = 0;
+	}
+
+	// True if the window is full and has no repeats
+	fn push(&mut self, ch:char) -> bool {
+		let count = self.counts.entry(ch).or_insert(0);
+		if *count > 0 { self.duplicates += 1 }
+		*count += 1;
+		self.back.push_back(ch);
+
+		if self.back.len() > self.window {
+			let old = self.back.pop_front().unwrap();
+			let count = self.counts.get_mut(&old).unwrap();
+			*count -= 1;
+			if *count > 0 { self.duplicates -= 1 }
+		}
+
+		self.back.len() == self.window && self.duplicates == 0
+	}
+}
+
+fn marker_name(window:usize) -> String {
+	match window {
+		4 => "start-of-packet".to_string(),
+		14 => "start-of-message".to_string(),
+		_ => format!("{}-distinct", window)
+	}
+}
 
 fn main() -> Result<(), Error> {
-    // Load file from command-line argument or (if none) stdin
-	let filename = std::env::args().fuse().nth(1);
-	let mut chars = match &filename {
-		None => return Err(Error::new(ErrorKind::InvalidInput, "File argument expected")),
-		Some(x) => CharReader::new(std::fs::File::open(x)?)
+	let (filename, windows, all) = {
+		let cli = Cli::parse();
+		(cli.filename, cli.window, cli.all)
 	};
+	if windows.contains(&0) { return Err(Error::new(ErrorKind::InvalidInput, "Window must be at least 1")) }
 
-	let mut seen: usize = 0;
-	let mut back: [char; BACK] = [' '; BACK];
+	// Load file from command-line argument or (if none or -) stdin
+	let mut chars: CharReader<Either<Stdin, File>> = CharReader::new(match filename.as_deref() {
+		None | Some("-") => either::Left(stdin()),
+		Some(x) => either::Right(std::fs::File::open(x)?)
+	});
 
-	let invalid = || { return Err(Error::new(ErrorKind::InvalidInput, "No repeating characters")) };
+	let mut detectors: Vec<Detector> = windows.iter().map(|&window| Detector::new(window)).collect();
+	let mut found: Vec<bool> = vec![false; windows.len()];
+	let mut seen: usize = 0; // Non-whitespace characters so far in this stream
+	let mut line_num: usize = 1;
 
-	// Scan file
-	while let Ok(Some(ch)) = chars.next_char() {
-		if ch.is_whitespace() { continue }
+	// Report any markers never found, then start next stream
+	let end_stream = |detectors:&mut [Detector], found:&mut [bool], seen:usize, line_num:usize| {
+		if seen > 0 {
+			for (idx, &window) in windows.iter().enumerate() {
+				if !found[idx] { println!("Line {}: no {} marker", line_num, marker_name(window)) }
+			}
+		}
+		for detector in detectors.iter_mut() { detector.clear() }
+		found.fill(false);
+	};
 
-		back[seen % BACK] = ch;
+	// Scan input. Each line is a separate datastream.
+	while let Some(ch) = chars.next_char()? {
+		if ch == '\n' {
+			end_stream(&mut detectors, &mut found, seen, line_num);
+			seen = 0;
+			line_num += 1;
+			continue
+		}
+		if ch.is_whitespace() { continue }
 		seen += 1;
 
-		//println!("{} in {:?}", ch, back);
-
-		let repeated = seen <= BACK
-			|| 'repeated: {
-				for left_idx in 0..(BACK-1) {
-					for right_idx in 0..BACK {
-						if left_idx != right_idx && back[left_idx] == back[right_idx] {
-							break 'repeated true
-						}
-					}
-				}
-				false
-			};
-
-		if !repeated {
-			println!("{}", seen); // Success
-			return Ok(())
+		//println!("{} in {:?}", ch, detectors[0].back);
+
+		for (idx, detector) in detectors.iter_mut().enumerate() {
+			if detector.push(ch) && (all || !found[idx]) {
+				println!("Line {}: {} marker at {}", line_num, marker_name(windows[idx]), seen); // Success
+				found[idx] = true;
+			}
 		}
 	}
+	end_stream(&mut detectors, &mut found, seen, line_num);
 
-	// Should not escape loop
-	invalid()
+	Ok(())
 }
@@ -1,57 +1,510 @@
-// Snoop a command line history and print the sum of all directories larger than 100kb
+// Snoop a command line history, rebuild the filesystem it describes, and find the smallest directory
+// to delete to get the disk under the target size (--disk total size, --free space required).
+// --plan N instead lists the N best sets of non-nested directories to delete, ranked by bytes wasted.
+// Queries: --lookup PATH shows one entry, --du PATH sizes every directory under PATH, --find-size [+-]N[kMG]
+// lists entries by size (files, or directories with --type d). With any query, only the queries print.
+// --export json|tree dumps the whole filesystem with cumulative sizes, like tree -J --du or tree --du.
+// --materialize DIR recreates it under DIR as sparse files of the listed sizes; names that would
+// escape DIR ("..", "a/b", ...) are refused, as is anything already there.
+// Directories live in an arena indexed by NodeId, and every traversal uses an explicit stack,
+// so arbitrarily deep trees are fine. The indented dump of the tree is only printed with DEBUG, as its
+// indentation grows with depth; --export shows the tree instead.
 // Has various problems:
-// - Will crash on too-deep stack depth.
 // - Can't handle Unicode input (or at least not Unicode whitespace).
-// - Memory inefficient.
-// - Assumes no spaces in filenames, which is probably fine?
+// - Directories dropped by a re-listing stay in the arena, unreachable.
+// - --plan is exact, so gives up on filesystems with too many ways to add up directory sizes.
 
 use std::io::{BufRead, BufReader, Error, ErrorKind, Stdin, stdin};
 use std::fs::File;
-use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::collections::{BTreeMap, BTreeSet};
 use either::Either;
-use std::rc::Rc;
-use std::cell::RefCell;
+use clap::Parser as CliParser;
 
-const GOODSIZE:u64 = 70_000_000-30_000_000;
+#[derive(Debug,Copy,Clone,PartialEq,clap::ValueEnum)]
+enum EntryType {
+	F, // Files
+	D  // Directories
+}
+
+#[derive(Debug,Copy,Clone,PartialEq,clap::ValueEnum)]
+enum ExportFormat {
+	Json,
+	Tree
+}
+
+#[derive(CliParser)]
+struct Cli {
+	#[arg(long = "lookup")]
+	lookup: Option<String>,
+	#[arg(long = "du")]
+	du: Option<String>,
+	#[arg(long = "find-size", allow_hyphen_values = true)]
+	find_size: Option<String>,
+	#[arg(long = "type", value_enum, default_value_t = EntryType::F)]
+	entry_type: EntryType,
+	#[arg(long = "disk", default_value_t = 70_000_000)]
+	disk: u64,
+	#[arg(long = "free", default_value_t = 30_000_000)]
+	free: u64,
+	#[arg(long = "plan")]
+	plan: Option<usize>,
+	#[arg(long = "export", value_enum)]
+	export: Option<ExportFormat>,
+	#[arg(long = "materialize")]
+	materialize: Option<String>,
+	filename: Option<String>
+}
+
+type NodeId = usize;
+const ROOT:NodeId = 0;
+const DEBUG:bool = false;
+const MAX_PLAN_WORDS:usize = 32_000_000; // 64-bit words --plan may use to track sizes
 
 #[derive(Default)]
 struct Dir {
-	dir:HashMap<String, Rc<RefCell<Dir>>>,
-	size:u64
+	parent:NodeId, // Root is its own parent
+	dir:BTreeMap<String, NodeId>,
+	file:BTreeMap<String, u64>
+}
+
+// What --plan tracks: sizes up to limit, and of those at least need, only the count smallest
+struct SizeBounds { limit:u64, need:u64, count:usize }
+
+// A set of sizes. Those under need are a sorted list or, once that would be bigger, a bitset; there are
+// only ever count of the rest, which stay a sorted list.
+struct Sizes {
+	below:Vec<u64>,
+	dense:bool,
+	above:Vec<u64>
+}
+
+// Set bits of a bitset from from on, in order
+fn bits_from(bits:&[u64], from:u64) -> impl Iterator<Item=u64> + '_ {
+	let first = (from/64) as usize;
+	bits.iter().enumerate().skip(first).flat_map(move |(idx, &word)| {
+		let mut word = if idx == first { word & !0u64 << (from%64) } else { word };
+		std::iter::from_fn(move || {
+			if word == 0 { return None }
+			let bit = word.trailing_zeros() as u64;
+			word &= word - 1;
+			Some(idx as u64 * 64 + bit)
+		})
+	})
+}
+
+impl Sizes {
+	fn new(sizes:Vec<u64>) -> Self { Sizes { below:sizes, dense:false, above:Vec::new() } }
+
+	fn words(&self) -> usize { self.below.len() + self.above.len() }
+
+	fn contains(&self, size:u64, bounds:&SizeBounds) -> bool {
+		if size >= bounds.need { return self.above.binary_search(&size).is_ok() }
+		if !self.dense { return self.below.binary_search(&size).is_ok() }
+		self.below[(size/64) as usize] & 1<<(size%64) != 0
+	}
+
+	// Sizes under need, from from on
+	fn below_from(&self, from:u64) -> Box<dyn Iterator<Item=u64> + '_> {
+		if self.dense { Box::new(bits_from(&self.below, from)) }
+		else { Box::new(self.below[self.below.partition_point(|&size| size < from)..].iter().copied()) }
+	}
+
+	// Everything in skip, plus everything in take grown by shift, within bounds
+	fn either(skip:&Sizes, take:&Sizes, shift:u64, bounds:&SizeBounds) -> Sizes {
+		let words = (bounds.need/64) as usize + 1;
+
+		let mut above:Vec<u64> = skip.above.iter().copied()
+			.chain(take.below_from(bounds.need.saturating_sub(shift)).take(bounds.count).map(|size| size + shift))
+			.chain(take.above.iter().map(|&size| size + shift))
+			.filter(|&size| size <= bounds.limit).collect();
+		above.sort_unstable();
+		above.dedup();
+		above.truncate(bounds.count);
+
+		let shifted = || take.below_from(0).map(|size| size + shift).take_while(|&size| size < bounds.need);
+		if !skip.dense && !take.dense {
+			let mut below:Vec<u64> = skip.below.iter().copied().chain(shifted()).collect();
+			below.sort_unstable();
+			below.dedup();
+			if below.len() <= words { return Sizes { below, dense:false, above } }
+			return Sizes { below:Sizes::new(below).to_bits(words), dense:true, above }
+		}
+		let mut below = skip.to_bits(words);
+		if take.dense && shift < bounds.need {
+			let (word_shift, bit_shift) = ((shift/64) as usize, shift%64);
+			for (src, dst) in below.iter_mut().skip(word_shift).enumerate() {
+				let mut word = take.below[src] << bit_shift;
+				if bit_shift > 0 && src > 0 { word |= take.below[src-1] >> (64-bit_shift) }
+				*dst |= word;
+			}
+			below[words-1] &= !(!0u64 << (bounds.need%64)); // Those of at least need are in above
+		} else {
+			for size in shifted() { below[(size/64) as usize] |= 1<<(size%64) }
+		}
+		Sizes { below, dense:true, above }
+	}
+
+	fn to_bits(&self, words:usize) -> Vec<u64> {
+		if self.dense { return self.below.clone() }
+		let mut bits = vec![0; words];
+		for &size in &self.below { bits[(size/64) as usize] |= 1<<(size%64) }
+		bits
+	}
+}
+
+// Arena of directories; ROOT is always present
+struct Fs {
+	dirs:Vec<Dir>
+}
+
+enum Entry {
+	Dir(NodeId),
+	File(u64)
+}
+
+impl Fs {
+	fn new() -> Self { Fs { dirs: vec![Dir::default()] } }
+
+	// Get or create subdirectory
+	fn child(&mut self, at:NodeId, name:&str) -> NodeId {
+		if let Some(&id) = self.dirs[at].dir.get(name) { return id }
+		let id = self.dirs.len();
+		self.dirs.push(Dir { parent:at, ..Default::default() });
+		self.dirs[at].dir.insert(name.to_string(), id);
+		id
+	}
+
+	// Resolve an absolute path (relative paths are taken from root) without creating anything
+	fn lookup(&self, path:&str) -> Option<Entry> {
+		let mut at = ROOT;
+		let mut parts = path.split('/').filter(|x| !x.is_empty() && *x != ".").peekable();
+		while let Some(part) = parts.next() {
+			if part == ".." { at = self.dirs[at].parent; continue }
+			match self.dirs[at].dir.get(part) {
+				Some(&id) => at = id,
+				None => {
+					let size = *self.dirs[at].file.get(part)?;
+					return if parts.peek().is_none() { Some(Entry::File(size)) } else { None }
+				}
+			}
+		}
+		Some(Entry::Dir(at))
+	}
+
+	fn path(&self, id:NodeId) -> String {
+		let mut names:Vec<&str> = Vec::new();
+		let mut at = id;
+		while at != ROOT {
+			let parent = self.dirs[at].parent;
+			names.push(self.dirs[parent].dir.iter().find(|&(_, &child)| child == at).unwrap().0);
+			at = parent;
+		}
+		names.reverse();
+		format!("/{}", names.join("/"))
+	}
+
+	// Subdirectories and files together, sorted by name
+	fn entries(&self, id:NodeId) -> Vec<(&str, Entry)> {
+		let d = &self.dirs[id];
+		let mut entries:Vec<(&str, Entry)> = d.dir.iter().map(|(k, &child)| (k.as_str(), Entry::Dir(child)))
+			.chain(d.file.iter().map(|(k, &size)| (k.as_str(), Entry::File(size)))).collect();
+		entries.sort_by_key(|x| x.0);
+		entries
+	}
+
+	// Directories under (and including) from, children before parents
+	fn post_order(&self, from:NodeId) -> Vec<NodeId> {
+		let mut order:Vec<NodeId> = Vec::new();
+		let mut stack:Vec<NodeId> = vec![from];
+		while let Some(id) = stack.pop() { // Parents before children, reversed at the end
+			order.push(id);
+			stack.extend(self.dirs[id].dir.values());
+		}
+		order.reverse();
+		order
+	}
+
+	// Total size of each directory under from, indexed by NodeId (0 for directories outside from)
+	fn totals(&self, from:NodeId) -> Vec<u64> {
+		let mut totals:Vec<u64> = vec![0; self.dirs.len()];
+		for id in self.post_order(from) {
+			let d = &self.dirs[id];
+			totals[id] = d.file.values().sum::<u64>() + d.dir.values().map(|&child| totals[child]).sum::<u64>();
+		}
+		totals
+	}
+
+	// Up to count sets of non-nested directories under from that free at least need bytes, smallest first,
+	// one set per distinct size. Knapsack over directories in preorder: each is either deleted whole,
+	// skipping its subtree, or kept, moving on to its children. Sizes past the count-th smallest single
+	// directory that frees enough can't make the list, and nor can any but the count smallest of at least
+	// need, so only the rest are tracked (see Sizes). Errors if that would take over MAX_PLAN_WORDS.
+	fn plan(&self, from:NodeId, totals:&[u64], need:u64, count:usize) -> Result<Vec<(u64, Vec<NodeId>)>, Error> {
+		let mut singles:Vec<u64> = self.post_order(from).into_iter().map(|id| totals[id]).filter(|&size| size >= need).collect();
+		singles.sort_unstable();
+		singles.dedup();
+		if count == 0 || singles.is_empty() { return Ok(Vec::new()) }
+		let limit = singles[count.min(singles.len()) - 1];
+
+		// Directories small enough to delete whole, in preorder, with the index just past each subtree
+		let mut items:Vec<(NodeId, usize)> = Vec::new();
+		{
+			enum Step { Enter(NodeId), Leave(usize) }
+			let mut stack:Vec<Step> = vec![Step::Enter(from)];
+			while let Some(step) = stack.pop() {
+				match step {
+					Step::Enter(id) => {
+						if totals[id] <= limit && totals[id] > 0 { // Empty directories free nothing, so never list them
+							stack.push(Step::Leave(items.len()));
+							items.push((id, 0));
+						}
+						for &child in self.dirs[id].dir.values().rev() { stack.push(Step::Enter(child)) }
+					},
+					Step::Leave(idx) => items[idx].1 = items.len()
+				}
+			}
+		}
+
+		// reachable[i] holds s if some choice among items i.. (respecting skips) totals s
+		let bounds = SizeBounds { limit, need, count };
+		let mut budget = MAX_PLAN_WORDS;
+		let mut reachable:Vec<Sizes> = Vec::with_capacity(items.len()+1);
+		reachable.resize_with(items.len()+1, || Sizes::new(Vec::new()));
+		reachable[items.len()] = Sizes::new(vec![0]);
+		for (idx, &(id, end)) in items.iter().enumerate().rev() {
+			let sizes = Sizes::either(&reachable[idx+1], &reachable[end], totals[id], &bounds);
+			budget = budget.checked_sub(sizes.words()).ok_or_else(|| Error::new(ErrorKind::InvalidInput,
+				format!("Too many possible sizes to plan (over {} words); try a smaller --plan", MAX_PLAN_WORDS)))?;
+			reachable[idx] = sizes;
+		}
+
+		Ok(reachable[0].above.iter().map(|&size| {
+			let mut ids:Vec<NodeId> = Vec::new();
+			let (mut idx, mut rest) = (0, size);
+			while rest > 0 {
+				let (id, end) = items[idx];
+				if totals[id] <= rest && reachable[end].contains(rest - totals[id], &bounds) {
+					ids.push(id);
+					rest -= totals[id];
+					idx = end;
+				} else {
+					idx += 1;
+				}
+			}
+			(size, ids)
+		}).collect())
+	}
+
+	// Calls visit(path, size, is_dir) for every entry under from, children before parents
+	fn walk(&self, from:NodeId, from_path:&str, visit:&mut dyn FnMut(&str, u64, bool)) {
+		enum Step<'a> {
+			Enter(NodeId, Option<&'a str>), // Name is None for from itself
+			Leave(NodeId, usize)            // Path length to restore
+		}
+		// Path is built in place, so a deep chain costs O(depth) rather than a string copy per level
+		fn push_name(path:&mut String, name:&str) {
+			if !path.ends_with('/') { path.push('/') }
+			path.push_str(name)
+		}
+
+		let totals = self.totals(from);
+		let mut path = from_path.to_string();
+		let mut stack:Vec<Step> = vec![Step::Enter(from, None)];
+		while let Some(step) = stack.pop() {
+			match step {
+				Step::Enter(id, name) => {
+					let before = path.len();
+					if let Some(name) = name { push_name(&mut path, name) }
+					let dir_len = path.len();
+					for (k,v) in &self.dirs[id].file {
+						push_name(&mut path, k);
+						visit(&path, *v, false);
+						path.truncate(dir_len);
+					}
+					stack.push(Step::Leave(id, before));
+					for (k, &child) in self.dirs[id].dir.iter().rev() { stack.push(Step::Enter(child, Some(k))) }
+				},
+				Step::Leave(id, before) => {
+					visit(&path, totals[id], true);
+					path.truncate(before);
+				}
+			}
+		}
+	}
+}
+
+fn print_tree(fs:&Fs, totals:&[u64]) {
+	enum Step<'a> {
+		Dir(&'a str, NodeId, usize), // Heading line, then contents
+		Files(NodeId, usize)
+	}
+	let indent = |depth:usize| { for _ in 0..depth { print!("\t") } };
+	let mut stack:Vec<Step> = vec![Step::Files(ROOT, 0)];
+	for (k, &child) in fs.dirs[ROOT].dir.iter().rev() { stack.push(Step::Dir(k, child, 0)) }
+	while let Some(step) = stack.pop() {
+		match step {
+			Step::Dir(name, id, depth) => {
+				indent(depth);
+				println!("{}/: {}", name, totals[id]);
+				stack.push(Step::Files(id, depth+1));
+				for (k, &child) in fs.dirs[id].dir.iter().rev() { stack.push(Step::Dir(k, child, depth+1)) }
+			},
+			Step::Files(id, depth) => {
+				for (k,v) in &fs.dirs[id].file {
+					indent(depth);
+					println!("{}: {}", k, v);
+				}
+			}
+		}
+	}
+}
+
+fn json_string(s:&str) -> String {
+	let mut result = String::from("\"");
+	for ch in s.chars() {
+		match ch {
+			'"' => result.push_str("\\\""),
+			'\\' => result.push_str("\\\\"),
+			'\n' => result.push_str("\\n"),
+			'\t' => result.push_str("\\t"),
+			_ if (ch as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", ch as u32)),
+			_ => result.push(ch)
+		}
+	}
+	result.push('"');
+	result
 }
 
-fn print_tree(d:&Dir, depth:usize) {
-	for (k,v) in &d.dir {
-		for _ in 0..depth { print!("\t") }
-		println!("{}: {}", k, v.borrow().size);
-		print_tree(&v.borrow(), depth+1);
+// Same shape as tree -J --du: one entry per line, directories hold their contents
+fn print_json(fs:&Fs, totals:&[u64]) {
+	enum Step<'a> {
+		Entry(&'a str, Entry, usize, bool), // Depth, and whether a comma goes first
+		Close(usize)
+	}
+	let indent = |depth:usize| "  ".repeat(depth);
+	println!("[");
+	let mut stack:Vec<Step> = vec![Step::Entry("/", Entry::Dir(ROOT), 1, false)];
+	while let Some(step) = stack.pop() {
+		match step {
+			Step::Entry(name, entry, depth, comma) => {
+				if comma { println!(",") }
+				match entry {
+					Entry::File(size) => print!("{}{{\"type\":\"file\",\"name\":{},\"size\":{}}}", indent(depth), json_string(name), size),
+					Entry::Dir(id) => {
+						print!("{}{{\"type\":\"directory\",\"name\":{},\"size\":{},\"contents\":[", indent(depth), json_string(name), totals[id]);
+						let entries = fs.entries(id);
+						if !entries.is_empty() { println!() }
+						stack.push(Step::Close(if entries.is_empty() { 0 } else { depth }));
+						for (idx, (k, child)) in entries.into_iter().enumerate().rev() {
+							stack.push(Step::Entry(k, child, depth+1, idx > 0))
+						}
+					}
+				}
+			},
+			Step::Close(0) => print!("]}}"),
+			Step::Close(depth) => print!("\n{}]}}", indent(depth))
+		}
 	}
+	println!("\n]");
 }
 
-fn total_filesize(d:&Dir) -> u64 {
-	let mut total = d.size;
-	for d2 in d.dir.values() {
-		total += total_filesize(&d2.borrow())
+// Same shape as tree --du -s
+fn print_tree_drawing(fs:&Fs, totals:&[u64]) {
+	let (mut dir_count, mut file_count) = (0, 0);
+	println!("[{:>11}]  /", totals[ROOT]);
+	type Step<'a> = (&'a str, Entry, String, bool); // Name, entry, prefix, is last in directory
+	fn push_entries<'a>(stack:&mut Vec<Step<'a>>, fs:&'a Fs, id:NodeId, prefix:&str) {
+		let entries = fs.entries(id);
+		let last = entries.len();
+		for (idx, (k, child)) in entries.into_iter().enumerate().rev() {
+			stack.push((k, child, prefix.to_string(), idx+1 == last))
+		}
+	}
+	let mut stack:Vec<Step> = Vec::new();
+	push_entries(&mut stack, fs, ROOT, "");
+	while let Some((name, entry, prefix, last)) = stack.pop() {
+		let branch = if last { "└── " } else { "├── " };
+		match entry {
+			Entry::File(size) => {
+				file_count += 1;
+				println!("{}{}[{:>11}]  {}", prefix, branch, size, name)
+			},
+			Entry::Dir(id) => {
+				dir_count += 1;
+				println!("{}{}[{:>11}]  {}", prefix, branch, totals[id], name);
+				push_entries(&mut stack, fs, id, &format!("{}{}", prefix, if last { "    " } else { "│   " }));
+			}
+		}
 	}
-	return total
+	println!("\n{} directories, {} files", dir_count, file_count);
+}
+
+// A name that stays inside its directory when joined to it: exactly one plain path component, so no
+// separators, "." or "..", and no roots or prefixes like Windows' "C:". NUL is refused too, as no OS allows it.
+fn safe_name(name:&str) -> bool {
+	let components:Vec<Component> = Path::new(name).components().collect();
+	matches!(components[..], [Component::Normal(part)] if part == name) && !name.contains('\0')
 }
 
-fn delete_candidate_filesize(d:&Dir, deletion_target:u64) -> (u64, u64) {
-	let (mut total, mut result) = (d.size, u64::MAX);
-	for d2 in d.dir.values() {
-		let (subtotal, subresult) = delete_candidate_filesize(&d2.borrow(), deletion_target);
-		total += subtotal;
-		if result > subresult { result = subresult }
+// Recreate fs under target (created if missing) as directories and sparse files.
+// Never follows or replaces anything already present below target.
+fn materialize(fs:&Fs, target:&Path) -> Result<(), Error> {
+	let unsafe_name = |name:&str| { Error::new(ErrorKind::InvalidInput, format!("Refusing to materialize unsafe name '{}'", name)) };
+	for id in fs.post_order(ROOT) { // Check everything before writing anything
+		let d = &fs.dirs[id];
+		if let Some(k) = d.dir.keys().chain(d.file.keys()).find(|k| !safe_name(k)) { return Err(unsafe_name(k)) }
 	}
-	(
-		total, 
-		if total >= deletion_target && total < result {total} else {result}
-	)
+	std::fs::create_dir_all(target)?;
+	let mut stack:Vec<(NodeId, PathBuf)> = vec![(ROOT, target.to_path_buf())];
+	while let Some((id, path)) = stack.pop() {
+		let d = &fs.dirs[id];
+		for (k, &size) in &d.file {
+			let file = std::fs::OpenOptions::new().write(true).create_new(true).open(path.join(k))?;
+			file.set_len(size)?;
+		}
+		for (k, &child) in &d.dir {
+			let child_path = path.join(k);
+			std::fs::create_dir(&child_path)?;
+			stack.push((child, child_path));
+		}
+	}
+	Ok(())
+}
+
+// Like find -size, but in bytes unless suffixed k, M or G: "+N" more than N, "-N" less than N, "N" exactly N
+fn parse_size_filter(s:&str) -> Option<(std::cmp::Ordering, u64)> {
+	use std::cmp::Ordering;
+	let (ordering, rest) = match s.chars().next()? {
+		'+' => (Ordering::Greater, &s[1..]),
+		'-' => (Ordering::Less, &s[1..]),
+		_ => (Ordering::Equal, s)
+	};
+	let (number, unit) = match rest.char_indices().last()? {
+		(idx, 'k') => (&rest[..idx], 1<<10),
+		(idx, 'M') => (&rest[..idx], 1<<20),
+		(idx, 'G') => (&rest[..idx], 1<<30),
+		_ => (rest, 1)
+	};
+	Some((ordering, number.parse::<u64>().ok()?.checked_mul(unit)?))
 }
 
 fn main() -> Result<(), Error> {
+	let (filename, lookup_path, du_path, find_size, entry_type, disk, free, plan, export, materialize_dir) = {
+		let cli = Cli::parse();
+		(cli.filename, cli.lookup, cli.du, cli.find_size, cli.entry_type, cli.disk, cli.free, cli.plan, cli.export, cli.materialize)
+	};
+	if free > disk {
+		return Err(Error::new(ErrorKind::InvalidInput, format!("Can't free {} on a disk of size {}", free, disk)))
+	}
+	let good_size = disk - free;
+	let size_filter = match &find_size {
+		None => None,
+		Some(x) => Some(parse_size_filter(x).ok_or_else(||
+			Error::new(ErrorKind::InvalidInput, format!("Expected size like 100, +100k or -2M, got '{}'", x)))?)
+	};
+
     // Load file from command-line argument or (if none) stdin
-	let filename = std::env::args().fuse().nth(1);
 	let input: Either<BufReader<Stdin>, BufReader<File>> = match &filename {
 		None => either::Left(BufReader::new(stdin())),
 		Some(x) => either::Right(BufReader::new(std::fs::File::open(x)?))
@@ -59,101 +512,181 @@ fn main() -> Result<(), Error> {
 
 	let lines = input.lines();
 
-	let root:Rc<RefCell<Dir>> = Default::default();
+	let mut fs = Fs::new();
 
 	// Line parser
 	{
 		use pom::parser::*;
 
-		let mut pwd:Vec<Rc<RefCell<Dir>>> = Vec::new();
-		pwd.push(root.clone());
+		let mut pwd:NodeId = ROOT;
 
-		let invalid = |s:String| { return Err(Error::new(ErrorKind::InvalidInput, format!("Unrecognized line: '{}'", s))) };
+		// Names seen (dirs, files) since the last ls; on the next command, anything else in the directory is dropped
+		let mut listing:Option<(BTreeSet<String>, BTreeSet<String>)> = None;
+
+		let invalid = |line_num:usize, s:&str| { Error::new(ErrorKind::InvalidInput, format!("Line {}: Unrecognized line: '{}'", line_num, s)) };
 
 		enum Parsed {
-			Ls,         // Reset
-			Dir,        // Ignore
-	        Cd(String), // Change directory
-	        Size(u64)   // Listed
+			Ls,               // Start listing
+			Dir(String),      // Listed directory
+	        Cd(String),       // Change directory
+	        File(u64, String) // Listed file
 	    }
 
-//	    const ls_seq: [char; 2] = ['l', 's'];
-
 	    fn splode(s:&str) -> Vec<char> {
 	    	s.chars().collect()
 	    }
 
 		fn positive<'a>() -> Parser<'a, char, u64> {
-			let integer = one_of("123456789") - one_of("0123456789").repeat(0..) | sym('0');
+			let integer = (one_of("123456789") - one_of("0123456789").repeat(0..)) | sym('0');
 			integer.collect().convert(|s|String::from_iter(s.iter()).parse::<u64>())
 		}
 
 		fn whitespace<'a>() -> Parser<'a, char, ()>
 			{ one_of(" \t").repeat(1..).discard() }
 
+		// Rest of line, so names may contain spaces
+		fn name<'a>() -> Parser<'a, char, String>
+			{ any().repeat(1..).map(|x| x.iter().collect()) }
+
 		fn cli_prefix<'a>() -> Parser<'a, char, ()>
 			{ empty() - sym('$') - whitespace() }
 
 		fn cli_ls<'a>() -> Parser<'a, char, Parsed> {
-			let pattern = cli_prefix() * seq(&['l', 's']);
+			let pattern = cli_prefix() * seq(&['l', 's']) - whitespace().opt() - end();
 			pattern.map(|_| Parsed::Ls)
 		}
 		const DIR_SLICE:[char;3] = ['d', 'i', 'r'];
 		fn cli_dir<'a>() -> Parser<'a, char, Parsed> {
-			let pattern = empty() - seq(&DIR_SLICE) - whitespace() - none_of(" \t").repeat(1..);
-			pattern.map(|_| Parsed::Dir)
+			let pattern = seq(&DIR_SLICE) * whitespace() * name();
+			pattern.map(Parsed::Dir)
 		}
 		fn cli_size<'a>() -> Parser<'a, char, Parsed> {
-			let pattern = positive() - whitespace() - none_of(" \t").repeat(1..);
-			pattern.map(|x| Parsed::Size(x))
+			let pattern = positive() - whitespace() + name();
+			pattern.map(|(size, name)| Parsed::File(size, name))
 		}
 		fn cli_cd<'a>() -> Parser<'a, char, Parsed> {
 			let prefix = cli_prefix() - seq(&['c', 'd']) - whitespace();
-			let pattern = none_of(" \t").repeat(1..)
-				.map(|x| Parsed::Cd(x.iter().collect()));
-			prefix * pattern
+			prefix * name().map(Parsed::Cd)
 		}
 		fn cli_line<'a>() -> Parser<'a, char, Parsed>
 			{ cli_ls() | cli_dir() | cli_size() | cli_cd() }
 
+		fn finish_listing(d:&mut Dir, listing:Option<(BTreeSet<String>, BTreeSet<String>)>) {
+			if let Some((dirs, files)) = listing {
+				d.dir.retain(|k, _| dirs.contains(k));
+				d.file.retain(|k, _| files.contains(k));
+			}
+		}
+
 		// Scan file
-		for line in lines {
+		for (line_idx, line) in lines.enumerate() {
 			let line = line?;
+			let line_num = line_idx + 1;
 			let line_array:Vec<char> = splode(&line);
-			let content = cli_line().parse(&line_array);
+			let content = cli_line().parse(&line_array).map_err(|_| invalid(line_num, &line))?;
+			let is_file = |name:&str| { Error::new(ErrorKind::InvalidInput, format!("Line {}: '{}' is a file", line_num, name)) };
+			let is_dir = |name:&str| { Error::new(ErrorKind::InvalidInput, format!("Line {}: '{}' is a directory", line_num, name)) };
+
 			match content {
-				Ok(Parsed::Ls) => pwd.last().unwrap().borrow_mut().size = 0,
-				Ok(Parsed::Dir) => (),
-				Ok(Parsed::Cd(s)) => {
-					match s.as_str() {
-						"/" => pwd.truncate(1),
-						".." => if pwd.len() > 1 { pwd.pop(); },
-						_ => {
-							let d = pwd.last().unwrap().borrow_mut()
-								.dir.entry(s).or_default().clone();
-							pwd.push( d )
+				Parsed::Ls => {
+					finish_listing(&mut fs.dirs[pwd], listing.take());
+					listing = Some(Default::default());
+				},
+				Parsed::Cd(path) => {
+					finish_listing(&mut fs.dirs[pwd], listing.take());
+					if path.starts_with('/') { pwd = ROOT }
+					for part in path.split('/') {
+						match part {
+							"" | "." => (),
+							".." => pwd = fs.dirs[pwd].parent,
+							_ => {
+								if fs.dirs[pwd].file.contains_key(part) { return Err(is_file(part)) }
+								pwd = fs.child(pwd, part)
+							}
 						}
-					};
+					}
 				},
-				Ok(Parsed::Size(s)) => pwd.last().unwrap().borrow_mut().size += s,
-				_ => return invalid(line)
+				Parsed::Dir(name) => {
+					if fs.dirs[pwd].file.contains_key(&name) { return Err(is_file(&name)) }
+					fs.child(pwd, &name);
+					if let Some((dirs, _)) = &mut listing { dirs.insert(name); }
+				},
+				Parsed::File(size, name) => {
+					let cwd = &mut fs.dirs[pwd];
+					if cwd.dir.contains_key(&name) { return Err(is_dir(&name)) }
+					cwd.file.insert(name.clone(), size);
+					if let Some((_, files)) = &mut listing { files.insert(name); }
+				}
 			}
 		}
+		finish_listing(&mut fs.dirs[pwd], listing.take());
 	}
 
-	print_tree(&root.borrow(), 0);
+	// Queries
+	if lookup_path.is_some() || du_path.is_some() || size_filter.is_some() || export.is_some() || materialize_dir.is_some() {
+		let not_found = |path:&str| { Error::new(ErrorKind::NotFound, format!("No such file or directory: '{}'", path)) };
 
-	let invalid_size = || { return Err(Error::new(ErrorKind::InvalidInput, format!("Filesystem is already under target size {}", GOODSIZE))) };
+		if let Some(path) = &lookup_path {
+			match fs.lookup(path).ok_or_else(||not_found(path))? {
+				Entry::File(size) => println!("{}: {}", path, size),
+				Entry::Dir(id) => {
+					let d = &fs.dirs[id];
+					println!("{}: {}", path, fs.totals(id)[id]);
+					for k in d.dir.keys() { println!("dir {}", k) }
+					for (k,v) in &d.file { println!("{} {}", v, k) }
+				}
+			}
+		}
+
+		if let Some(path) = &du_path {
+			match fs.lookup(path).ok_or_else(||not_found(path))? {
+				Entry::File(size) => println!("{}\t{}", size, path),
+				Entry::Dir(id) => fs.walk(id, path, &mut |path, size, is_dir| if is_dir { println!("{}\t{}", size, path) })
+			}
+		}
+
+		if let Some((ordering, limit)) = size_filter {
+			fs.walk(ROOT, "/", &mut |path, size, is_dir| {
+				if is_dir == (entry_type == EntryType::D) && size.cmp(&limit) == ordering { println!("{}", path) }
+			});
+		}
+
+		match export {
+			None => (),
+			Some(ExportFormat::Json) => print_json(&fs, &fs.totals(ROOT)),
+			Some(ExportFormat::Tree) => print_tree_drawing(&fs, &fs.totals(ROOT))
+		}
+
+		if let Some(dir) = &materialize_dir {
+			materialize(&fs, Path::new(dir))?;
+		}
+
+		return Ok(())
+	}
+
+	let totals = fs.totals(ROOT);
+	if DEBUG { print_tree(&fs, &totals); }
+
+	let invalid_size = || { Err(Error::new(ErrorKind::InvalidInput, format!("Filesystem is already under target size {}", good_size))) };
 
 	{ // Final score
-		let target_size = total_filesize(&root.borrow());
+		let target_size = totals[ROOT];
 		println!("Total size {}", target_size);
-		if target_size <= GOODSIZE { return invalid_size() }
+		if target_size <= good_size { return invalid_size() }
 
-		let deletion_target = target_size-GOODSIZE;
+		let deletion_target = target_size-good_size;
 		println!("Deletion target {}", deletion_target);
 
-		let (_, size) = delete_candidate_filesize(&root.borrow(), deletion_target);
+		if let Some(count) = plan {
+			for (idx, (size, ids)) in fs.plan(ROOT, &totals, deletion_target, count)?.into_iter().enumerate() {
+				let paths:Vec<String> = ids.into_iter().map(|id| fs.path(id)).collect();
+				println!("Plan {}: frees {}, wastes {}: {}", idx+1, size, size-deletion_target, paths.join(", "));
+			}
+			return Ok(())
+		}
+
+		let size = fs.post_order(ROOT).into_iter().map(|id| totals[id])
+			.filter(|&size| size >= deletion_target).min().unwrap();
 		println!("{}", size);
 	}
 
@@ -1,12 +1,171 @@
 // Finds "invisible" cells in a height map
+// Each line of cells is swept once per direction with a monotonic stack, so the whole map is O(W·H).
+// --directions picks the lines of sight: 4 (default), 8 (with diagonals), and/or slopes like 1/2
+// (dy/dx, both ways along it), comma-separated. A slope only passes through the cells it hits exactly.
+// Heights are one digit per cell, or with --list whitespace- or comma-separated numbers from 0 to 127.
+// --heatmap colours visible trees by height in the terminal; --ppm FILE writes the same as an image.
 
 use std::io::{BufRead, BufReader, Error, ErrorKind, Stdin, stdin};
 use std::fs::File;
 use either::Either;
+use clap::Parser;
+
+#[derive(Parser)]
+struct Cli {
+	// Heights are separated numbers rather than one digit per cell
+	#[arg(short = 'l', long = "list")]
+	list: bool,
+	#[arg(long = "heatmap")]
+	heatmap: bool,
+	#[arg(long = "ppm")]
+	ppm: Option<String>,
+	// Pixels per cell in the image
+	#[arg(long = "scale", default_value_t = 8)]
+	scale: usize,
+	#[arg(short = 'd', long = "directions", default_value = "4", value_parser = parse_directions)]
+	directions: Directions,
+	filename: Option<String>
+}
+
+type Height = i8;
+type Rgb = (u8, u8, u8);
+
+// Row-major heights
+struct Grid {
+	width:usize,
+	height:usize,
+	cells:Vec<Height>
+}
+
+// A line of cells through the grid: first index, stride between cells, length
+type Line = (usize, isize, usize);
+
+// Step between cells of a line of sight, (dx, dy) in lowest terms
+type Direction = (isize, isize);
+
+#[derive(Clone)]
+struct Directions(Vec<Direction>);
+
+fn gcd(a:isize, b:isize) -> isize { if b == 0 { a.abs() } else { gcd(b, a % b) } }
+
+// "4", "8" or "dy/dx", comma-separated; slopes go both ways
+fn parse_directions(s:&str) -> Result<Directions, String> {
+	let mut directions:Vec<Direction> = Vec::new();
+	for item in s.split(',').map(str::trim) {
+		let added:Vec<Direction> = match item {
+			"4" => vec![(1,0), (-1,0), (0,1), (0,-1)],
+			"8" => vec![(1,0), (-1,0), (0,1), (0,-1), (1,1), (-1,-1), (1,-1), (-1,1)],
+			_ => {
+				let invalid = || format!("Expected 4, 8 or a slope like 1/2, got '{}'", item);
+				let (dy, dx) = item.split_once('/').ok_or_else(invalid)?;
+				let (dy, dx) = (dy.trim().parse::<isize>().map_err(|_| invalid())?, dx.trim().parse::<isize>().map_err(|_| invalid())?);
+				if dx == 0 && dy == 0 { return Err(invalid()) }
+				let divisor = gcd(dx, dy);
+				vec![(dx/divisor, dy/divisor), (-dx/divisor, -dy/divisor)]
+			}
+		};
+		for direction in added { if !directions.contains(&direction) { directions.push(direction) } }
+	}
+	Ok(Directions(directions))
+}
+
+impl Grid {
+	// Steps from (x, y) along direction until leaving the grid, counting (x, y) itself
+	fn steps(&self, (x, y):(usize, usize), (dx, dy):Direction) -> usize {
+		let along = |at:usize, d:isize, size:usize| match d {
+			0 => usize::MAX,
+			_ if d > 0 => (size-1-at) / d as usize + 1,
+			_ => at / (-d) as usize + 1
+		};
+		along(x, dx, self.width).min(along(y, dy, self.height))
+	}
+
+	// Every line of sight running in direction, each starting at the edge cell it enters by
+	fn lines(&self, (dx, dy):Direction) -> Vec<Line> {
+		let (width, height) = (self.width as isize, self.height as isize);
+		let mut lines:Vec<Line> = Vec::new();
+		for y in 0..height {
+			for x in 0..width {
+				let (px, py) = (x-dx, y-dy);
+				if px >= 0 && px < width && py >= 0 && py < height { continue } // Not the first cell
+				let len = self.steps((x as usize, y as usize), (dx, dy));
+				lines.push(((y*width + x) as usize, dy*width + dx, len));
+			}
+		}
+		lines
+	}
+
+	// Walks a line, looking back toward its start from each cell. The stack holds (height, step) of the
+	// trees taller than everything between them and the current cell, so after popping the shorter ones,
+	// the top is the nearest tree at least as tall.
+	// Calls visit(idx, seen, blocked): how many trees it sees up to and including that one (or the edge),
+	// and whether there was one. Each tree is pushed and popped once, so O(length).
+	fn sweep(&self, (start, stride, len):Line, stack:&mut Vec<(Height, usize)>, visit:&mut dyn FnMut(usize, usize, bool)) {
+		stack.clear();
+		for step in 0..len {
+			let idx = (start as isize + stride*step as isize) as usize;
+			let tree = self.cells[idx];
+			while stack.last().is_some_and(|&(x, _)| x < tree) { stack.pop(); }
+			let (seen, blocked) = match stack.last() {
+				Some(&(_, at)) => (step - at, true),
+				None => (step, false)
+			};
+			if stack.last().is_some_and(|&(x, _)| x == tree) { stack.pop(); } // Closer, so blocks instead from now on
+			stack.push((tree, step));
+			visit(idx, seen, blocked);
+		}
+	}
+}
+
+// Dark blue through cyan and yellow to red, for t from 0 to 1
+fn heat(t:f64) -> Rgb {
+	const STOPS:[(f64, f64, f64); 4] = [(0.0, 0.0, 96.0), (0.0, 192.0, 255.0), (255.0, 224.0, 0.0), (224.0, 0.0, 0.0)];
+	let t = t.clamp(0.0, 1.0) * (STOPS.len()-1) as f64;
+	let idx = (t as usize).min(STOPS.len()-2);
+	let frac = t - idx as f64;
+	let (a, b) = (STOPS[idx], STOPS[idx+1]);
+	let lerp = |x:f64, y:f64| (x + (y-x)*frac).round() as u8;
+	(lerp(a.0, b.0), lerp(a.1, b.1), lerp(a.2, b.2))
+}
+
+// Two character cells on coloured backgrounds; the marked cell shows "<>"
+fn print_heatmap(width:usize, colours:&[Rgb], mark:Option<usize>) {
+	use ansi_term::{Colour, Style};
+	for (y, row) in colours.chunks(width).enumerate() {
+		for (x, &(r, g, b)) in row.iter().enumerate() {
+			let style = Style::new().on(Colour::RGB(r, g, b));
+			let text = if mark == Some(y*width + x) { "<>" } else { "  " };
+			print!("{}", style.fg(Colour::White).bold().paint(text));
+		}
+		println!();
+	}
+}
+
+// Binary PPM, scale pixels per cell; the marked cell gets a white outline
+fn write_ppm(path:&str, width:usize, height:usize, colours:&[Rgb], mark:Option<usize>, scale:usize) -> Result<(), Error> {
+	use std::io::Write;
+	let mut out = std::io::BufWriter::new(File::create(path)?);
+	write!(out, "P6\n{} {}\n255\n", width*scale, height*scale)?;
+	for py in 0..height*scale {
+		for px in 0..width*scale {
+			let idx = (py/scale)*width + px/scale;
+			let (cx, cy) = (px%scale, py%scale);
+			let edge = cx == 0 || cy == 0 || cx == scale-1 || cy == scale-1;
+			let (r, g, b) = if mark == Some(idx) && edge { (255, 255, 255) } else { colours[idx] };
+			out.write_all(&[r, g, b])?;
+		}
+	}
+	out.flush()
+}
 
 fn main() -> Result<(), Error> {
+	let (filename, list, heatmap, ppm, scale, directions) = {
+		let cli = Cli::parse();
+		(cli.filename, cli.list, cli.heatmap, cli.ppm, cli.scale, cli.directions.0)
+	};
+	if scale == 0 { return Err(Error::new(ErrorKind::InvalidInput, "Scale must be at least 1")) }
+
     // Load file from command-line argument or (if none) stdin
-	let filename = std::env::args().fuse().nth(1);
 	let input: Either<BufReader<Stdin>, BufReader<File>> = match &filename {
 		None => either::Left(BufReader::new(stdin())),
 		Some(x) => either::Right(BufReader::new(std::fs::File::open(x)?))
@@ -14,89 +173,81 @@ fn main() -> Result<(), Error> {
 
 	let lines = input.lines();
 
-	let mut grid: Vec<Vec<i8>> = Default::default();
-	let mut seen_grid: Vec<Vec<bool>>;
-
-	let invalid = || { return Err(Error::new(ErrorKind::InvalidInput, "Blank lines?")) };
-	let invalide2 = || { return Error::new(ErrorKind::InvalidInput, "Invalid characters") };
-	let invalid3 = || { return Err(Error::new(ErrorKind::InvalidInput, "Unequal lines")) };
-	let invalid4 = || { return Err(Error::new(ErrorKind::InvalidInput, "Empty input")) };
+	let invalid = || { Err(Error::new(ErrorKind::InvalidInput, "Blank lines?")) };
+	let invalide2 = || { Error::new(ErrorKind::InvalidInput, "Invalid characters") };
+	let invalid3 = || { Err(Error::new(ErrorKind::InvalidInput, "Unequal lines")) };
+	let invalid4 = || { Err(Error::new(ErrorKind::InvalidInput, "Empty input")) };
+	let invalid5 = |line_num:usize, s:&str| { Error::new(ErrorKind::InvalidInput, format!("Line {}: Invalid height '{}'", line_num, s)) };
 
 	// Parse file
-	let width = {
+	let grid = {
+		let mut cells: Vec<Height> = Default::default();
+		let mut height = 0;
 		let mut blank_state = 0; // 0,1,2
 		let mut width: Option<usize> = None;
-		for line in lines {
+		for (line_idx, line) in lines.enumerate() {
 			let line = line?;
+			let line_num = line_idx + 1;
 			let line = line.trim_end();
-			if line.is_empty() {
+			let separator = |ch:char| ch.is_whitespace() || ch == ',';
+			// A --list row of nothing but separators has no cells, so counts as blank
+			if line.is_empty() || (list && line.split(separator).all(|x| x.is_empty())) {
 				if blank_state == 1 { blank_state = 2 }
 				continue;
 			} else {
 				match blank_state { 0 => blank_state = 1, 2 => return invalid(), _=>() }
 			}
 
-			let row = line.chars().map(
-					|x|x.to_digit(10).map(|x|x as i8).ok_or_else(invalide2)
-				).collect::<Result<Vec<i8>, Error>>()?;
+			let before = cells.len();
+			if list {
+				for word in line.split(separator).filter(|x| !x.is_empty()) {
+					cells.push(word.parse::<u32>().ok().and_then(|x| Height::try_from(x).ok()).ok_or_else(|| invalid5(line_num, word))?);
+				}
+			} else {
+				for ch in line.chars() {
+					cells.push(ch.to_digit(10).map(|x|x as Height).ok_or_else(invalide2)?);
+				}
+			}
+			let row_len = cells.len() - before;
 
-			match width { None => width = Some(row.len()),
-				Some(x) => if x != row.len() { return invalid3() }}
+			match width { None => width = Some(row_len),
+				Some(x) => if x != row_len { return invalid3() }}
 
-			grid.push(row);
+			height += 1;
 		}
 
 		match width {
 			None => return invalid4(),
-			Some(width) => {
-				seen_grid = vec![vec![false; width]; grid.len()];
-				width
-			}
+			Some(width) => Grid { width, height, cells }
 		}
 	};
 
 	// Check visibility.
 	// Note 0 does NOT mean "no tree". It means a min-height tree.
-	let invisible = {
-		let height = grid.len();
-		let mut seen:usize = 0;
-		let mut check = |x:usize,y:usize,idx:usize,highest:&mut i8,pass_seen:&mut Vec<bool>| {
-			let pass_seen_cell:&mut bool = &mut pass_seen[idx];
-			if *pass_seen_cell { return true } // Met ourselves from other side, don't process further.
-
-			let seen_cell:&mut bool = &mut seen_grid[x][y];
-			let grid_cell = grid[x][y];
-			if grid_cell > *highest {
-				*highest = grid_cell;
-				*pass_seen_cell = true;
-				if !*seen_cell {
-					*seen_cell = true;
-					seen += 1;
-				}
+	let mut seen_grid = vec![false; grid.cells.len()];
+	{
+		let mut stack:Vec<(Height, usize)> = Vec::new();
+		for &direction in &directions {
+			for line in grid.lines(direction) {
+				grid.sweep(line, &mut stack, &mut |idx, _, blocked| if !blocked { seen_grid[idx] = true });
 			}
-			false
-		};
-		for x in 0..width {
-			let mut pass_seen = vec![false; width];
-			let mut highest:i8 = -1;
-			for y in 0..height { if check(x,y,y,&mut highest,&mut pass_seen) { break } }
-			let mut highest:i8 = -1;
-			for y in (0..height).rev() { if check(x,y,y,&mut highest,&mut pass_seen) { break } }
 		}
-		for y in 0..height {
-			let mut pass_seen = vec![false; height];
-			let mut highest:i8 = -1;
-			for x in 0..width { if check(x,y,x,&mut highest,&mut pass_seen) { break } }
-			let mut highest:i8 = -1;
-			for x in (0..width).rev() { if check(x,y,x,&mut highest,&mut pass_seen) { break } }
-		}
-		seen
-	};
+	}
+	let visible = seen_grid.iter().filter(|&&x| x).count();
 
-	for y in seen_grid { for x in y { print!("{}", if x {'█'} else {'.'}) } println!(""); }
+	if heatmap || ppm.is_some() {
+		let tallest = grid.cells.iter().copied().max().unwrap_or(0).max(1);
+		let colours:Vec<Rgb> = grid.cells.iter().zip(&seen_grid).map(|(&tree, &seen)|
+			if seen { heat(tree as f64 / tallest as f64) } else { (40, 40, 40) }).collect();
+		if heatmap { print_heatmap(grid.width, &colours, None) }
+		if let Some(path) = &ppm { write_ppm(path, grid.width, grid.height, &colours, None, scale)? }
+	}
+	if !heatmap {
+		for row in seen_grid.chunks(grid.width) { for &x in row { print!("{}", if x {'█'} else {'.'}) } println!(); }
+	}
 
 	// Final score
-	println!("{}", invisible);
+	println!("{}", visible);
 
 	Ok(())
 }
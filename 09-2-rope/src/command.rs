@@ -0,0 +1,139 @@
+// Command grammar: comma-separated moves like "R 4" or "UL 2" (U, D, L, R and the four diagonals),
+// repeat blocks like "3x(R 4, U 2)", which nest, and comments from '#' to the end of the line.
+
+pub enum Command {
+	Move((i32,i32), usize), // Step, count
+	Repeat(usize, Vec<Command>)
+}
+
+// Run commands in order, calling tick once per single step
+pub fn run(commands:&[Command], tick:&mut dyn FnMut((i32,i32))) {
+	for command in commands {
+		match command {
+			Command::Move(dir, count) => for _ in 0..*count { tick(*dir) },
+			Command::Repeat(count, body) => for _ in 0..*count { run(body, tick) }
+		}
+	}
+}
+
+fn direction(s:&str) -> Option<(i32,i32)> {
+	Some(match s {
+		"U" => (0,-1), "D" => (0,1), "L" => (-1,0), "R" => (1,0),
+		"UL" => (-1,-1), "UR" => (1,-1), "DL" => (-1,1), "DR" => (1,1),
+		_ => return None
+	})
+}
+
+#[derive(PartialEq)]
+enum Token { Number(usize), Word(String), Open, Close, Comma }
+
+impl std::fmt::Display for Token {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Token::Number(n) => write!(f, "{}", n),
+			Token::Word(s) => write!(f, "{}", s),
+			Token::Open => write!(f, "("),
+			Token::Close => write!(f, ")"),
+			Token::Comma => write!(f, ",")
+		}
+	}
+}
+
+// Tokens with their 1-indexed column
+fn tokenize(line:&str) -> Result<Vec<(usize, Token)>, String> {
+	let mut tokens:Vec<(usize, Token)> = Vec::new();
+	let mut chars = line.chars().enumerate().peekable();
+	while let Some((idx, ch)) = chars.next() {
+		let column = idx + 1;
+		let mut take_while = |first:char, pred:fn(&char) -> bool| {
+			let mut s = String::from(first);
+			while let Some((_, ch)) = chars.next_if(|(_, ch)| pred(ch)) { s.push(ch) }
+			s
+		};
+		let token = match ch {
+			'#' => break,
+			_ if ch.is_whitespace() => continue,
+			'(' => Token::Open,
+			')' => Token::Close,
+			',' => Token::Comma,
+			_ if ch.is_ascii_digit() => {
+				let s = take_while(ch, char::is_ascii_digit);
+				Token::Number(s.parse::<usize>().map_err(|_| format!("Number '{}' at column {} is too large", s, column))?)
+			},
+			_ if ch.is_alphabetic() => Token::Word(take_while(ch, |ch| ch.is_alphabetic())),
+			_ => return Err(format!("Unexpected '{}' at column {}", ch, column))
+		};
+		tokens.push((column, token));
+	}
+	Ok(tokens)
+}
+
+struct Parser {
+	tokens:Vec<(usize, Token)>,
+	pos:usize
+}
+
+impl Parser {
+	fn next(&mut self) -> Option<&(usize, Token)> {
+		let token = self.tokens.get(self.pos);
+		self.pos += 1;
+		token
+	}
+
+	fn unexpected(&self) -> String {
+		match self.tokens.get(self.pos-1) {
+			Some((column, token)) => format!("Unexpected '{}' at column {}", token, column),
+			None => String::from("Unexpected end of line")
+		}
+	}
+
+	fn expect(&mut self, want:Token) -> Result<(), String> {
+		match self.next() {
+			Some((_, token)) if *token == want => Ok(()),
+			_ => Err(self.unexpected())
+		}
+	}
+
+	// item (',' item)*, up to a ')' if nested, else the end of the line
+	fn list(&mut self, nested:bool) -> Result<Vec<Command>, String> {
+		let mut commands:Vec<Command> = Vec::new();
+		if !nested && self.tokens.is_empty() { return Ok(commands) } // Blank or comment-only line
+		loop {
+			commands.push(self.item()?);
+			match self.next() {
+				Some((_, Token::Comma)) => (),
+				Some((_, Token::Close)) if nested => return Ok(commands),
+				None if !nested => return Ok(commands),
+				_ => return Err(self.unexpected())
+			}
+		}
+	}
+
+	// DIR COUNT | COUNT 'x' '(' list ')'
+	fn item(&mut self) -> Result<Command, String> {
+		match self.next() {
+			Some((_, Token::Number(count))) => {
+				let count = *count;
+				match self.next() {
+					Some((_, Token::Word(x))) if x == "x" || x == "X" => (),
+					_ => return Err(self.unexpected())
+				}
+				self.expect(Token::Open)?;
+				Ok(Command::Repeat(count, self.list(true)?))
+			},
+			Some((column, Token::Word(word))) => {
+				let dir = direction(word).ok_or_else(|| format!("Unknown direction '{}' at column {}", word, column))?;
+				match self.next() {
+					Some(&(_, Token::Number(count))) => Ok(Command::Move(dir, count)),
+					_ => Err(self.unexpected())
+				}
+			},
+			_ => Err(self.unexpected())
+		}
+	}
+}
+
+// Errors name the offending token and its column; the caller adds the line
+pub fn parse_line(line:&str) -> Result<Vec<Command>, String> {
+	Parser { tokens:tokenize(line)?, pos:0 }.list(false)
+}
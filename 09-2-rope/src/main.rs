@@ -1,24 +1,45 @@
-// Intake a series of commands to move a two-cell "rope" on a grid
+// Intake a series of commands to move a multi-cell "rope" on a grid
+// Prints how many cells the last knot visited. --length sets the number of knots (default 10);
+// --knots prints the count for every knot, and --histogram how many cells each knot visited once, twice, ...
+// Commands may be diagonal, comma-separated, grouped into repeat blocks and commented; see command.rs.
+// --model picks how knots follow (snap, slack:K, cardinal, elastic; see physics.rs). Given more than once,
+// each model runs on the same commands and its results are prefixed with its name.
 
 use std::io::{BufRead, BufReader, Error, ErrorKind, Stdin, stdin};
 use std::fs::File;
 use either::Either;
 use hashbrown::HashMap;
-use hashbrown::hash_map::Entry;
-use itertools::Itertools;
 use std::cmp::{min, max};
+use std::collections::BTreeMap;
 use ndarray::{Axis, Array2};
+use clap::Parser;
 
 const DEBUG:bool = false;
-const ROPE_LEN:usize = 10;
 
 // "Debug line", "debug single"
-macro_rules! d { ( $( $x:expr ),* ) => { if (DEBUG) { println!($($x,)*) } }; }
-macro_rules! ds { ( $( $x:expr ),* ) => { if (DEBUG) { print!($($x,)*) } }; }
+macro_rules! d { ( $( $x:expr ),* ) => { if DEBUG { println!($($x,)*) } }; }
+macro_rules! ds { ( $( $x:expr ),* ) => { if DEBUG { print!($($x,)*) } }; }
+
+mod command;
+mod physics;
+
+use physics::Model;
+
+#[derive(Parser)]
+struct Cli {
+	#[arg(short = 'n', long = "length", default_value_t = 10)]
+	length: usize,
+	#[arg(long = "knots")]
+	knots: bool,
+	#[arg(long = "histogram")]
+	histogram: bool,
+	#[arg(short = 'm', long = "model", default_value = "snap", value_parser = physics::parse_model)]
+	model: Vec<Model>,
+	filename: Option<String>
+}
 
-#[derive(PartialEq,PartialOrd,Copy,Clone)]
-enum Cell { Empty, Roped, Headed, Tailed, Start }
-impl Default for Cell { fn default() -> Self { Cell::Empty } }
+#[derive(PartialEq,PartialOrd,Copy,Clone,Default)]
+enum Cell { #[default] Empty, Roped, Headed, Tailed, Start }
 
 type At = (i32,i32);
 
@@ -29,8 +50,13 @@ fn point_max((x,y):At, (x2,y2):At) -> At { (max(x,x2),max(y,y2)) }
 fn point_usize((x,y):At) -> (usize, usize) { (x as usize, y as usize) }
 
 fn main() -> Result<(), Error> {
+	let (filename, rope_len, show_knots, show_histogram, models) = {
+		let cli = Cli::parse();
+		(cli.filename, cli.length, cli.knots, cli.histogram, cli.model)
+	};
+	if rope_len == 0 { return Err(Error::new(ErrorKind::InvalidInput, "Rope needs at least one knot")) }
+
     // Load file from command-line argument or (if none) stdin
-	let filename = std::env::args().fuse().nth(1);
 	let input: Either<BufReader<Stdin>, BufReader<File>> = match &filename {
 		None => either::Left(BufReader::new(stdin())),
 		Some(x) => either::Right(BufReader::new(std::fs::File::open(x)?))
@@ -38,93 +64,91 @@ fn main() -> Result<(), Error> {
 
 	let lines = input.lines();
 
-	let mut total: i64 = 0;
-
-	let invalide =  || { Error::new(ErrorKind::InvalidInput, "Unrecognized command") };
-	let invalid =   || { Err(invalide()) };
-	let invalide2 = || { Error::new(ErrorKind::InvalidInput, "Non-integer argument") };
-
-	let mut map: HashMap<At, Cell> = Default::default();
-	let mut map_write = |at:At,v:Cell| {
-		let entry = map.entry(at);
-		if match &entry { Entry::Vacant(_) => true, Entry::Occupied(v2) => v>*v2.get()} {
-			entry.insert(v);
-		}
-	};
+	let invalid = |line_num:usize, s:String| { Error::new(ErrorKind::InvalidInput, format!("Line {}: {}", line_num, s)) };
 
 	// Scan file
-	{
-		let mut rope = [(0,0); ROPE_LEN];
-		map_write(rope[0], Cell::Start);
-
-		for line in lines {
-			let line = line?;
-			let (dir_str, num_str) = line.split_whitespace().collect_tuple().ok_or_else(invalide)?;
-			let dir = 
-				match dir_str {
-					"U" => (0,-1), "D" => (0,1), "L" => (-1,0), "R" => (1,0),
-					_ => return invalid()
-				};
-			let count = num_str.parse::<usize>().map_err(|_|invalide2())?;
-			for _ in 0..count {
-				rope[0] = point_add(rope[0], dir);
-				map_write(rope[0], Cell::Headed);
-d!("\t\t---");
-				for idx in 0..(ROPE_LEN-1) {
-					let (rope_left, rope_right) = rope.split_at_mut(idx+1);
-					let (head_at, tail_at) = (&rope_left[idx], &mut rope_right[0]);
-					let (xd,yd) = point_sub(*head_at,*tail_at);
-					let (xda, yda) = (xd.abs(), yd.abs());
-					let offset:At;
-					if xda>1 || yda>1 {
-						fn dir(i:i32) -> i32 {
-							if i < -1 { return 1 }
-							if i > 1  { return -1 }
-							return 0
-						}
-						offset = (dir(xd),dir(yd));
-						d!("head {:?} tail {:?} diff {},{} offset {:?}", *head_at, *tail_at, xd, yd, offset);
-						*tail_at = point_add(*head_at, offset);
-						d!("\ttail now: {:?}", *tail_at);
-						map_write(*tail_at, if idx < ROPE_LEN-2 {Cell::Roped} else {Cell::Tailed} );
-					} else {
-						d!("head {:?} tail {:?} diff {},{}", *head_at, *tail_at, xd, yd);
-					}
+	let mut commands: Vec<command::Command> = Default::default();
+	for (line_idx, line) in lines.enumerate() {
+		let line = line?;
+		let line_num = line_idx + 1;
+		commands.extend(command::parse_line(&line).map_err(|s| invalid(line_num, s))?);
+	}
+
+	for &model in &models {
+		let prefix = if models.len() > 1 { format!("{}: ", model) } else { String::new() };
+		let visits = simulate(&commands, rope_len, model);
+		if DEBUG { debug_map(&visits) }
+
+		if show_knots || show_histogram {
+			for (knot, cells) in visits.iter().enumerate() {
+				print!("{}Knot {}: {} cells", prefix, knot, cells.len());
+				if show_histogram {
+					// Times visited => number of cells visited that many times
+					let mut histogram: BTreeMap<usize, usize> = Default::default();
+					for &times in cells.values() { *histogram.entry(times).or_insert(0) += 1 }
+					let histogram:Vec<String> = histogram.iter().map(|(times, cells)| format!("{}x {}", times, cells)).collect();
+					print!(" ({})", histogram.join(", "));
 				}
+				println!();
 			}
 		}
+
+		// Final score
+		println!("{}{}", prefix, visits[rope_len-1].len());
 	}
 
-	if DEBUG {
-		let (mut min, mut max) = ((i32::MAX, i32::MAX), (i32::MIN, i32::MIN));
-		for k in map.keys() {
-			min = point_min(min, *k);
-			max = point_max(max, *k);
-		}
-		let (xs, ys) = point_add(point_sub(max,min), (1,1));
-		let mut grid:Array2<Cell> = Array2::default((xs as usize, ys as usize)); //[[Cell::Empty; ys]; xs];
-		for (k,v) in &map {
-			let (k,v) = (*k, *v);
-			grid[point_usize(point_sub(k, min))] = v;
-		}
-		for col in grid.axis_iter(Axis(1)) {
-			for v in col {
-				ds!("{}", match v {
-					Cell::Empty => '.', Cell::Headed => '█', Cell::Roped => '░', Cell::Tailed => '◊', Cell::Start => 'S'
-				})
-			}
-			d!("");
+	Ok(())
+}
+
+// Per knot, how many times it arrived at each cell (starting there counts once)
+fn simulate(commands:&[command::Command], rope_len:usize, model:Model) -> Vec<HashMap<At, usize>> {
+	let mut visits: Vec<HashMap<At, usize>> = vec![Default::default(); rope_len];
+	let mut visit = |knot:usize, at:At| { *visits[knot].entry(at).or_insert(0) += 1 };
+
+	let mut rope = vec![(0,0); rope_len];
+	let mut previous: Vec<At> = Vec::with_capacity(rope_len);
+	for (knot, &at) in rope.iter().enumerate() { visit(knot, at) }
+
+	command::run(commands, &mut |dir| {
+		rope[0] = point_add(rope[0], dir);
+		visit(0, rope[0]);
+d!("\t\t---");
+		physics::follow(model, &mut rope, &mut previous, &mut visit);
+	});
+
+	visits
+}
+
+// Each cell shows the "highest" of start, last knot, head, any other knot
+fn debug_map(visits:&[HashMap<At, usize>]) {
+	let mut map: HashMap<At, Cell> = Default::default();
+	for (knot, cells) in visits.iter().enumerate() {
+		let v = if knot == visits.len()-1 { Cell::Tailed } else if knot == 0 { Cell::Headed } else { Cell::Roped };
+		for &at in cells.keys() {
+			let cell = map.entry(at).or_default();
+			if v > *cell { *cell = v }
 		}
-		d!("");
 	}
+	map.insert((0,0), Cell::Start);
 
-	for v in map.values() {
-		let v = *v;
-		if v == Cell::Tailed || v == Cell::Start { total += 1 }
+	let (mut min, mut max) = ((i32::MAX, i32::MAX), (i32::MIN, i32::MIN));
+	for k in map.keys() {
+		min = point_min(min, *k);
+		max = point_max(max, *k);
 	}
-
-	// Final score
-	println!("{}", total);
-
-	Ok(())
+	let (xs, ys) = point_add(point_sub(max,min), (1,1));
+	let mut grid:Array2<Cell> = Array2::default((xs as usize, ys as usize)); //[[Cell::Empty; ys]; xs];
+	for (k,v) in &map {
+		let (k,v) = (*k, *v);
+		grid[point_usize(point_sub(k, min))] = v;
+	}
+	for col in grid.axis_iter(Axis(1)) {
+		for v in col {
+			ds!("{}", match v {
+				Cell::Empty => '.', Cell::Headed => '█', Cell::Roped => '░', Cell::Tailed => '◊', Cell::Start => 'S'
+			})
+		}
+		d!("");
+	}
+	d!("");
 }
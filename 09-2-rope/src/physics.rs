@@ -0,0 +1,105 @@
+// Rules for how each knot follows the one ahead of it after the head moves.
+
+use crate::{DEBUG, At, point_add, point_sub};
+
+#[derive(Copy,Clone)]
+pub enum Model {
+	Snap,       // Once not touching, jump to the cell next to the knot ahead (the puzzle's rule)
+	Slack(i32), // Step diagonally toward the knot ahead until within k in both axes; slack:1 acts like snap
+	Cardinal,   // Stay orthogonally adjacent, and only ever step up, down, left or right
+	Elastic     // Every knot takes at most one step per tick, toward where the knot ahead was last tick
+}
+
+// "snap", "slack:K", "cardinal" or "elastic"
+pub fn parse_model(s:&str) -> Result<Model, String> {
+	Ok(match s {
+		"snap" => Model::Snap,
+		"cardinal" => Model::Cardinal,
+		"elastic" => Model::Elastic,
+		_ => match s.strip_prefix("slack:").map(str::parse::<i32>) {
+			Some(Ok(k)) if k >= 1 => Model::Slack(k),
+			_ => return Err(format!("Expected snap, slack:K (K at least 1), cardinal or elastic, got '{}'", s))
+		}
+	})
+}
+
+impl std::fmt::Display for Model {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Model::Snap => write!(f, "snap"),
+			Model::Slack(k) => write!(f, "slack:{}", k),
+			Model::Cardinal => write!(f, "cardinal"),
+			Model::Elastic => write!(f, "elastic")
+		}
+	}
+}
+
+// One diagonal-or-straight step from at toward target
+fn step_toward(at:At, target:At) -> At {
+	let (xd, yd) = point_sub(target, at);
+	point_add(at, (xd.signum(), yd.signum()))
+}
+
+// Move every knot after the head, which has already moved. Calls visit(knot, at) for each cell a knot steps into.
+// previous is scratch space for the elastic model.
+pub fn follow(model:Model, rope:&mut [At], previous:&mut Vec<At>, visit:&mut dyn FnMut(usize, At)) {
+	match model {
+		Model::Snap => {
+			for idx in 0..(rope.len()-1) {
+				let (rope_left, rope_right) = rope.split_at_mut(idx+1);
+				let (head_at, tail_at) = (&rope_left[idx], &mut rope_right[0]);
+				let (xd,yd) = point_sub(*head_at,*tail_at);
+				let (xda, yda) = (xd.abs(), yd.abs());
+				let offset:At;
+				if xda>1 || yda>1 {
+					fn dir(i:i32) -> i32 {
+						if i < -1 { return 1 }
+						if i > 1  { return -1 }
+						0
+					}
+					offset = (dir(xd),dir(yd));
+					d!("head {:?} tail {:?} diff {},{} offset {:?}", *head_at, *tail_at, xd, yd, offset);
+					*tail_at = point_add(*head_at, offset);
+					d!("\ttail now: {:?}", *tail_at);
+					visit(idx+1, *tail_at);
+				} else {
+					d!("head {:?} tail {:?} diff {},{}", *head_at, *tail_at, xd, yd);
+				}
+			}
+		},
+		Model::Slack(k) => {
+			for idx in 1..rope.len() {
+				loop {
+					let (xd, yd) = point_sub(rope[idx-1], rope[idx]);
+					if xd.abs() <= k && yd.abs() <= k { break }
+					rope[idx] = step_toward(rope[idx], rope[idx-1]);
+					visit(idx, rope[idx]);
+				}
+			}
+		},
+		Model::Cardinal => {
+			for idx in 1..rope.len() {
+				loop {
+					let (xd, yd) = point_sub(rope[idx-1], rope[idx]);
+					if xd.abs() + yd.abs() <= 1 { break }
+					let offset = if xd.abs() >= yd.abs() { (xd.signum(), 0) } else { (0, yd.signum()) };
+					rope[idx] = point_add(rope[idx], offset);
+					visit(idx, rope[idx]);
+				}
+			}
+		},
+		Model::Elastic => {
+			// Knot 1 sees where the head is now; each later knot, where the one ahead was before this tick
+			previous.clear();
+			previous.extend_from_slice(rope);
+			for idx in 1..rope.len() {
+				let target = previous[idx-1];
+				let (xd, yd) = point_sub(target, rope[idx]);
+				if xd.abs() > 1 || yd.abs() > 1 {
+					rope[idx] = step_toward(rope[idx], target);
+					visit(idx, rope[idx]);
+				}
+			}
+		}
+	}
+}
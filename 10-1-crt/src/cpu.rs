@@ -0,0 +1,169 @@
+// Cycle-accurate emulator for the handheld's CPU.
+// Instructions come from a table giving each one's operands and cycle cost; labels ("name:" on a line
+// of their own) may be jumped to from anywhere. Arithmetic wraps. Peripherals on the bus watch the
+// registers every cycle.
+
+use std::io::{Error, ErrorKind};
+use std::collections::HashMap;
+
+pub const REG_NAMES:[&str; 2] = ["x", "y"];
+pub type Registers = [i64; REG_NAMES.len()];
+
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum Op { Noop, Addx, Addy, Mulx, Muly, Jmp, Jz, Jnz, Jgt }
+
+#[derive(Copy,Clone,PartialEq)]
+enum Kind {
+	Value,    // Number or register
+	Register,
+	Label
+}
+
+struct OpInfo {
+	name:&'static str,
+	op:Op,
+	cycles:u32,
+	operands:&'static [Kind]
+}
+
+const OPS:[OpInfo; 9] = [
+	OpInfo { name:"noop", op:Op::Noop, cycles:1, operands:&[] },
+	OpInfo { name:"addx", op:Op::Addx, cycles:2, operands:&[Kind::Value] },
+	OpInfo { name:"addy", op:Op::Addy, cycles:2, operands:&[Kind::Value] },
+	OpInfo { name:"mulx", op:Op::Mulx, cycles:3, operands:&[Kind::Value] },
+	OpInfo { name:"muly", op:Op::Muly, cycles:3, operands:&[Kind::Value] },
+	OpInfo { name:"jmp",  op:Op::Jmp,  cycles:1, operands:&[Kind::Label] },
+	OpInfo { name:"jz",   op:Op::Jz,   cycles:1, operands:&[Kind::Register, Kind::Label] },  // Jump if register is 0
+	OpInfo { name:"jnz",  op:Op::Jnz,  cycles:1, operands:&[Kind::Register, Kind::Label] },
+	OpInfo { name:"jgt",  op:Op::Jgt,  cycles:1, operands:&[Kind::Register, Kind::Value, Kind::Label] } // Jump if register > value
+];
+
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum Operand {
+	Number(i64),
+	Register(usize),
+	Target(usize) // Instruction index a label points at
+}
+
+impl Operand {
+	fn value(&self, regs:&Registers) -> i64 {
+		match *self {
+			Operand::Number(n) => n,
+			Operand::Register(r) => regs[r],
+			Operand::Target(_) => unreachable!("Labels are never read as values")
+		}
+	}
+}
+
+pub struct Instruction {
+	pub op:Op,
+	pub operands:Vec<Operand>,
+	pub cycles:u32,
+	pub line_num:usize,
+	pub text:String
+}
+
+fn register(s:&str) -> Option<usize> { REG_NAMES.iter().position(|&x| x == s) }
+
+// Lines are instructions, "label:", or blank
+pub fn parse_program(lines:impl Iterator<Item=Result<String, Error>>) -> Result<Vec<Instruction>, Error> {
+	let invalid = |line_num:usize, s:String| { Error::new(ErrorKind::InvalidInput, format!("Line {}: {}", line_num, s)) };
+
+	let mut labels:HashMap<String, usize> = HashMap::new();
+	let mut pending:Vec<(usize, Vec<String>)> = Vec::new(); // Line number, label operands
+	let mut program:Vec<Instruction> = Vec::new();
+
+	for (line_idx, line) in lines.enumerate() {
+		let line = line?;
+		let line_num = line_idx + 1;
+		let mut tokens = line.split_whitespace();
+		let keyword = match tokens.next() { None => continue, Some(x) => x };
+		if let Some(label) = keyword.strip_suffix(':') {
+			if tokens.next().is_some() { return Err(invalid(line_num, format!("Expected nothing after label '{}'", label))) }
+			if labels.insert(label.to_string(), program.len()).is_some() { return Err(invalid(line_num, format!("Duplicate label '{}'", label))) }
+			continue
+		}
+		let info = OPS.iter().find(|x| x.name == keyword).ok_or_else(|| invalid(line_num, format!("Unrecognized command '{}'", keyword)))?;
+		let args:Vec<&str> = tokens.collect();
+		if args.len() != info.operands.len() {
+			return Err(invalid(line_num, format!("{} takes {} operand(s), got {}", info.name, info.operands.len(), args.len())))
+		}
+		let mut operands:Vec<Operand> = Vec::new();
+		let mut label_args:Vec<String> = Vec::new();
+		for (&kind, &arg) in info.operands.iter().zip(&args) {
+			let bad = || invalid(line_num, format!("Bad operand '{}' for {}", arg, info.name));
+			operands.push(match kind {
+				Kind::Value => match register(arg) {
+					Some(r) => Operand::Register(r),
+					None => Operand::Number(arg.parse::<i64>().map_err(|_| bad())?)
+				},
+				Kind::Register => Operand::Register(register(arg).ok_or_else(bad)?),
+				Kind::Label => { label_args.push(arg.to_string()); Operand::Target(0) } // Resolved below
+			});
+		}
+		pending.push((line_num, label_args));
+		program.push(Instruction { op:info.op, operands, cycles:info.cycles, line_num, text:line.trim().to_string() });
+	}
+
+	// Labels may come after the jumps that use them
+	for (instruction, (line_num, label_args)) in program.iter_mut().zip(pending) {
+		let mut label_args = label_args.into_iter();
+		for operand in instruction.operands.iter_mut() {
+			if let Operand::Target(target) = operand {
+				let label = label_args.next().unwrap();
+				*target = *labels.get(&label).ok_or_else(|| invalid(line_num, format!("Unknown label '{}'", label)))?;
+			}
+		}
+	}
+
+	Ok(program)
+}
+
+// Something on the bus, shown the cycle number (from 1) and registers during each cycle
+pub trait Peripheral {
+	fn cycle(&mut self, cycle:i64, regs:&Registers);
+}
+
+#[derive(Default)]
+pub struct Cpu {
+	pub regs:Registers,
+	pub pc:usize,       // Instruction running, or next to start
+	pub cycle:i64,      // Cycles finished
+	pub remaining:u32   // Cycles left in the running instruction; 0 between instructions
+}
+
+impl Cpu {
+	pub fn new() -> Self { let mut cpu = Cpu::default(); cpu.regs[0] = 1; cpu }
+
+	pub fn halted(&self, program:&[Instruction]) -> bool { self.remaining == 0 && self.pc >= program.len() }
+
+	// Run one cycle. The bus sees the registers as they are during it; an instruction ending on
+	// this cycle takes effect after. Returns true if one did.
+	pub fn tick(&mut self, program:&[Instruction], bus:&mut [&mut dyn Peripheral]) -> bool {
+		let instruction = &program[self.pc];
+		if self.remaining == 0 { self.remaining = instruction.cycles }
+		self.cycle += 1;
+		for peripheral in bus.iter_mut() { peripheral.cycle(self.cycle, &self.regs) }
+		self.remaining -= 1;
+		if self.remaining > 0 { return false }
+
+		let regs = &mut self.regs;
+		let arg = |idx:usize, regs:&Registers| instruction.operands[idx].value(regs);
+		let target = |idx:usize| match instruction.operands[idx] { Operand::Target(t) => t, _ => unreachable!() };
+		let reg = |idx:usize| match instruction.operands[idx] { Operand::Register(r) => r, _ => unreachable!() };
+		let mut next = self.pc + 1;
+		match instruction.op {
+			Op::Noop => (),
+			Op::Addx => regs[0] = regs[0].wrapping_add(arg(0, regs)),
+			Op::Addy => regs[1] = regs[1].wrapping_add(arg(0, regs)),
+			Op::Mulx => regs[0] = regs[0].wrapping_mul(arg(0, regs)),
+			Op::Muly => regs[1] = regs[1].wrapping_mul(arg(0, regs)),
+			Op::Jmp => next = target(0),
+			Op::Jz => if regs[reg(0)] == 0 { next = target(1) },
+			Op::Jnz => if regs[reg(0)] != 0 { next = target(1) },
+			Op::Jgt => if regs[reg(0)] > arg(1, regs) { next = target(2) }
+		}
+		self.pc = next;
+		true
+	}
+}
@@ -0,0 +1,230 @@
+// Runs the CPU a cycle at a time, keeping register history, writing a per-cycle CSV trace,
+// and offering an interactive debugger with breakpoints on cycle, instruction or register value.
+
+use std::io::{BufRead, BufWriter, Error, ErrorKind, Write, stdin};
+use std::fs::File;
+use crate::cpu::{Cpu, Instruction, Peripheral, Registers, REG_NAMES};
+
+const MAX_CYCLES:i64 = 10_000_000; // Jumps make endless programs possible
+
+// One cycle: its number, the instruction running, and the registers during it
+#[derive(Clone,Copy)]
+pub struct Record {
+	pub cycle:i64,
+	pub pc:usize,
+	pub regs:Registers
+}
+
+pub struct Session<'a> {
+	pub cpu:Cpu,
+	program:&'a [Instruction],
+	history:Option<Vec<Record>>, // Only kept when debugging
+	trace:Option<BufWriter<File>>
+}
+
+fn csv_field(s:&str) -> String {
+	if s.contains([',', '"']) { format!("\"{}\"", s.replace('"', "\"\"")) } else { s.to_string() }
+}
+
+impl<'a> Session<'a> {
+	pub fn new(program:&'a [Instruction], keep_history:bool, trace_path:Option<&str>) -> Result<Self, Error> {
+		let trace = match trace_path {
+			None => None,
+			Some(path) => {
+				let mut out = BufWriter::new(File::create(path)?);
+				writeln!(out, "cycle,pc,line,instruction,{}", REG_NAMES.join(","))?;
+				Some(out)
+			}
+		};
+		Ok(Session { cpu:Cpu::new(), program, history:keep_history.then(Vec::new), trace })
+	}
+
+	pub fn halted(&self) -> bool { self.cpu.halted(self.program) }
+
+	// Run one cycle, recording it first. Returns true if an instruction finished.
+	pub fn tick(&mut self, bus:&mut [&mut dyn Peripheral]) -> Result<bool, Error> {
+		if self.cpu.cycle >= MAX_CYCLES {
+			return Err(Error::new(ErrorKind::InvalidInput, format!("Program still running after {} cycles", MAX_CYCLES)))
+		}
+		let record = Record { cycle:self.cpu.cycle+1, pc:self.cpu.pc, regs:self.cpu.regs };
+		if let Some(history) = &mut self.history { history.push(record) }
+		if let Some(out) = &mut self.trace {
+			let instruction = &self.program[record.pc];
+			let regs:Vec<String> = record.regs.iter().map(|x| x.to_string()).collect();
+			writeln!(out, "{},{},{},{},{}", record.cycle, record.pc, instruction.line_num, csv_field(&instruction.text), regs.join(","))?;
+		}
+		Ok(self.cpu.tick(self.program, bus))
+	}
+
+	pub fn run(&mut self, bus:&mut [&mut dyn Peripheral]) -> Result<(), Error> {
+		while !self.halted() { self.tick(bus)?; }
+		self.finish()
+	}
+
+	pub fn finish(&mut self) -> Result<(), Error> {
+		match &mut self.trace { Some(out) => out.flush(), None => Ok(()) }
+	}
+}
+
+#[derive(Copy,Clone)]
+enum Compare { Eq, Ne, Lt, Le, Gt, Ge }
+
+impl Compare {
+	fn parse(s:&str) -> Option<Compare> {
+		Some(match s { "==" => Compare::Eq, "!=" => Compare::Ne, "<" => Compare::Lt, "<=" => Compare::Le, ">" => Compare::Gt, ">=" => Compare::Ge, _ => return None })
+	}
+	fn test(&self, a:i64, b:i64) -> bool {
+		match self { Compare::Eq => a == b, Compare::Ne => a != b, Compare::Lt => a < b, Compare::Le => a <= b, Compare::Gt => a > b, Compare::Ge => a >= b }
+	}
+	fn symbol(&self) -> &str {
+		match self { Compare::Eq => "==", Compare::Ne => "!=", Compare::Lt => "<", Compare::Le => "<=", Compare::Gt => ">", Compare::Ge => ">=" }
+	}
+}
+
+enum Breakpoint {
+	Cycle(i64),                     // Before this cycle runs
+	Pc(usize),                      // Before this instruction starts
+	Watch(usize, Compare, i64)      // When a register comes to meet the condition
+}
+
+impl std::fmt::Display for Breakpoint {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Breakpoint::Cycle(n) => write!(f, "cycle {}", n),
+			Breakpoint::Pc(n) => write!(f, "instruction {}", n),
+			Breakpoint::Watch(reg, compare, n) => write!(f, "{} {} {}", REG_NAMES[*reg], compare.symbol(), n)
+		}
+	}
+}
+
+impl Breakpoint {
+	// Checked between cycles; before is the register state one cycle earlier, for watches, or None
+	// before the first cycle of a continue, when a watch can't have just come true
+	fn hit(&self, cpu:&Cpu, before:Option<&Registers>) -> bool {
+		match *self {
+			Breakpoint::Cycle(n) => cpu.cycle+1 == n,
+			Breakpoint::Pc(n) => cpu.remaining == 0 && cpu.pc == n,
+			Breakpoint::Watch(reg, compare, n) => before.is_some_and(|before| compare.test(cpu.regs[reg], n) && !compare.test(before[reg], n))
+		}
+	}
+
+	fn parse(words:&[&str]) -> Option<Breakpoint> {
+		let register = |s:&str| REG_NAMES.iter().position(|&x| x == s);
+		Some(match *words {
+			["cycle", n] => Breakpoint::Cycle(n.parse().ok()?),
+			["pc", n] => Breakpoint::Pc(n.parse().ok()?),
+			[reg, compare, n] => Breakpoint::Watch(register(reg)?, Compare::parse(compare)?, n.parse().ok()?),
+			_ => return None
+		})
+	}
+}
+
+const HELP:&str = "\
+c                continue to a breakpoint or the end
+s [N]            step N instructions (default 1)
+t [N]            step N cycles (default 1)
+b cycle N        break before cycle N
+b pc N           break before instruction N (from 0)
+b REG OP N       break when REG comes to meet OP N; OP is == != < <= > >=
+l                list breakpoints
+d N              delete breakpoint N
+r                show registers
+h [N]            register history for the last N cycles (default 10)
+q                quit";
+
+fn show_state(session:&Session) {
+	let cpu = &session.cpu;
+	let regs:Vec<String> = REG_NAMES.iter().zip(cpu.regs).map(|(name, x)| format!("{}={}", name, x)).collect();
+	match session.program.get(cpu.pc) {
+		None => println!("After cycle {}: halted, {}", cpu.cycle, regs.join(" ")),
+		Some(instruction) => {
+			let progress = if cpu.remaining == 0 { "next".to_string() } else { format!("{} cycle(s) left", cpu.remaining) };
+			println!("After cycle {}: {}, instruction {} (line {}: {}, {})",
+				cpu.cycle, regs.join(" "), cpu.pc, instruction.line_num, instruction.text, progress);
+		}
+	}
+}
+
+// Read commands from stdin until the user quits or stdin ends
+pub fn interactive(session:&mut Session, bus:&mut [&mut dyn Peripheral]) -> Result<(), Error> {
+	let mut breakpoints:Vec<Option<Breakpoint>> = Vec::new(); // Deleted ones leave a gap so numbers stay put
+	let mut reported:(i64, Vec<usize>) = (-1, Vec::new()); // Cycle and breakpoints last stopped at, so c moves on
+	let mut lines = stdin().lock().lines();
+	println!("{} instructions loaded; h for register history, ? for help", session.program.len());
+	show_state(session);
+	loop {
+		print!("(debug) ");
+		std::io::stdout().flush()?;
+		let line = match lines.next() { None => break, Some(line) => line? };
+		let words:Vec<&str> = line.split_whitespace().collect();
+		let count = |default:usize| -> Option<usize> { match words.get(1) { None => Some(default), Some(n) => n.parse().ok() } };
+		match words.first().copied() {
+			None => continue,
+			Some("c") => {
+				let mut before:Option<Registers> = None;
+				loop {
+					let cpu = &session.cpu;
+					let hits:Vec<usize> = breakpoints.iter().enumerate()
+						.filter(|(idx, breakpoint)| breakpoint.as_ref().is_some_and(|breakpoint| breakpoint.hit(cpu, before.as_ref()))
+							&& !(reported.0 == cpu.cycle && reported.1.contains(idx)))
+						.map(|(idx, _)| idx).collect();
+					for &idx in &hits { println!("Breakpoint {}: {}", idx, breakpoints[idx].as_ref().unwrap()) }
+					if !hits.is_empty() { reported = (cpu.cycle, hits); break }
+					if session.halted() { break }
+					before = Some(session.cpu.regs);
+					session.tick(bus)?;
+				}
+				show_state(session);
+			},
+			Some("s") => match count(1) {
+				None => println!("Expected a count"),
+				Some(n) => {
+					for _ in 0..n {
+						if session.halted() { break }
+						while !session.tick(bus)? {}
+					}
+					show_state(session);
+				}
+			},
+			Some("t") => match count(1) {
+				None => println!("Expected a count"),
+				Some(n) => {
+					for _ in 0..n {
+						if session.halted() { break }
+						session.tick(bus)?;
+					}
+					show_state(session);
+				}
+			},
+			Some("b") => match Breakpoint::parse(&words[1..]) {
+				None => println!("Expected b cycle N, b pc N or b REG OP N"),
+				Some(breakpoint) => {
+					println!("Added breakpoint {}: {}", breakpoints.len(), breakpoint);
+					breakpoints.push(Some(breakpoint));
+				}
+			},
+			Some("l") => for (idx, breakpoint) in breakpoints.iter().enumerate() {
+				if let Some(breakpoint) = breakpoint { println!("{}: {}", idx, breakpoint) }
+			},
+			Some("d") => match words.get(1).and_then(|n| n.parse::<usize>().ok()).and_then(|n| breakpoints.get_mut(n)) {
+				Some(breakpoint @ Some(_)) => *breakpoint = None,
+				_ => println!("No such breakpoint")
+			},
+			Some("r") => show_state(session),
+			Some("h") => match (count(10), &session.history) {
+				(Some(n), Some(history)) => {
+					println!("cycle\tpc\t{}", REG_NAMES.join("\t"));
+					for record in &history[history.len().saturating_sub(n)..] {
+						let regs:Vec<String> = record.regs.iter().map(|x| x.to_string()).collect();
+						println!("{}\t{}\t{}", record.cycle, record.pc, regs.join("\t"));
+					}
+				},
+				_ => println!("Expected a count")
+			},
+			Some("q") => break,
+			Some("?") | Some("help") => println!("{}", HELP),
+			Some(x) => println!("Unknown command '{}'; ? for help", x)
+		}
+	}
+	session.finish()
+}
@@ -1,63 +1,71 @@
 // Timing emulator for a simple CPU
+// Sums the signal strength (cycle times X) on cycles 20, 60, 100, ... as seen by a probe on the bus.
+// The instruction set is in cpu.rs.
+// --trace FILE writes every cycle to CSV; --debug runs under an interactive debugger (see debugger.rs),
+// taking commands from stdin, so the program must come from a file.
 
 use std::io::{BufRead, BufReader, Error, ErrorKind, Stdin, stdin};
 use std::fs::File;
 use either::Either;
+use clap::Parser;
+
+mod cpu;
+mod debugger;
+
+use cpu::{Peripheral, Registers};
+
+#[derive(Parser)]
+struct Cli {
+	#[arg(long = "debug")]
+	debug: bool,
+	#[arg(long = "trace")]
+	trace: Option<String>,
+	filename: Option<String>
+}
 
 const EVERY_CYCLE:bool = false;
 
+// Signal-strength probe
+#[derive(Default)]
+struct Probe {
+	total:i64
+}
+
+impl Peripheral for Probe {
+	fn cycle(&mut self, cycle:i64, regs:&Registers) {
+		if EVERY_CYCLE || (cycle>20 && (cycle-20)%40 == 0) || cycle==20 {
+			let score = cycle * regs[0];
+			println!("Cycle {} Register {} score {}", cycle, regs[0], score);
+			self.total += score;
+		}
+	}
+}
+
 fn main() -> Result<(), Error> {
+	let (filename, debug, trace) = {
+		let cli = Cli::parse();
+		(cli.filename, cli.debug, cli.trace)
+	};
+	if debug && filename.is_none() {
+		return Err(Error::new(ErrorKind::InvalidInput, "--debug reads commands from stdin, so the program must be in a file"))
+	}
+
     // Load file from command-line argument or (if none) stdin
-	let filename = std::env::args().fuse().nth(1);
 	let input: Either<BufReader<Stdin>, BufReader<File>> = match &filename {
 		None => either::Left(BufReader::new(stdin())),
 		Some(x) => either::Right(BufReader::new(std::fs::File::open(x)?))
 	};
 
-	let lines = input.lines();
-
-	let mut total: i64 = 0;
-
-	let invalide = || { Error::new(ErrorKind::InvalidInput, "Invalid number argument") };
-	let invalid2 = || { Err(Error::new(ErrorKind::InvalidInput, "Unrecognized command")) };
-
-	let mut reg: i64 = 1;
-	let mut cycle:i64 = 0;
-	let mut advance = |x, reg:&i64| {
-		let mut x = x;
-		while x>0 {
-			x -= 1;
-			cycle += 1;
-			if EVERY_CYCLE || (cycle>20 && (cycle-20)%40 == 0) || cycle==20 {
-				let score = cycle * *reg;
-				println!("Cycle {} Register {} score {}", cycle, reg, score);
-				total += score;
-			}
-		}
-	};
+	let program = cpu::parse_program(input.lines())?;
 
-	// Scan file
-	for line in lines {
-		let line = line?;
-		if line.is_empty() {continue}
-		let mut tokens = line.split_whitespace().fuse();
-		let keyword = tokens.next().unwrap();
-		match keyword {
-			"noop" => { advance(1,&reg); }
-			"addx" => {
-				let x = tokens.next().ok_or_else(invalide)?.parse::<i64>().map_err(|_|invalide())?;
-				advance(2,&reg);
-				reg += x;
-			}
-			_ => {
-				return invalid2()
-			}
-		}
-	}
+	let mut session = debugger::Session::new(&program, debug, trace.as_deref())?;
+	let mut probe = Probe::default();
+	if debug { debugger::interactive(&mut session, &mut [&mut probe])? } else { session.run(&mut [&mut probe])? }
+	let cpu = &session.cpu;
 
 	// Final score
-	println!("Final cycles = {} register = {}", cycle, reg);
-	println!("{}", total);
+	println!("Final cycles = {} register = {}", cpu.cycle, cpu.regs[0]);
+	println!("{}", probe.total);
 
 	Ok(())
 }
@@ -0,0 +1,49 @@
+// The CRT's 4x6 capital letters, one per 5 columns (4 of glyph, 1 of gap), and a reader for them.
+
+pub const GLYPH_WIDTH:usize = 4;
+pub const GLYPH_HEIGHT:usize = 6;
+const CELL_WIDTH:usize = GLYPH_WIDTH + 1;
+
+const FONT:[(char, [&str; GLYPH_HEIGHT]); 18] = [
+	('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+	('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+	('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+	('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+	('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+	('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+	('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+	('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+	('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+	('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+	('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+	('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+	('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+	('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+	('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+	('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+	('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+	(' ', ["....", "....", "....", "....", "....", "...."])
+];
+
+// Reads the glyphs across a width x GLYPH_HEIGHT frame (row-major, true is lit).
+// Unknown glyphs, or anything lit in a gap column, come out as '?'; the bool is false if there were any.
+pub fn read(frame:&[bool], width:usize) -> (String, bool) {
+	let lit = |x:usize, y:usize| frame[y*width + x];
+	let mut text = String::new();
+	let mut all_known = true;
+	for cell in 0..width.div_ceil(CELL_WIDTH) {
+		let left = cell*CELL_WIDTH;
+		let gap_clear = (left+GLYPH_WIDTH..(left+CELL_WIDTH).min(width)).all(|x| (0..GLYPH_HEIGHT).all(|y| !lit(x, y)));
+		let found = FONT.iter().find(|(_, rows)| {
+			rows.iter().enumerate().all(|(y, row)| row.chars().enumerate().all(|(dx, ch)| {
+				let x = left + dx;
+				(x < width && lit(x, y)) == (ch == '#')
+			}))
+		});
+		match found {
+			Some((ch, _)) if gap_clear => text.push(*ch),
+			_ => { text.push('?'); all_known = false }
+		}
+	}
+	(text.trim_end().to_string(), all_known)
+}
@@ -1,60 +1,83 @@
-// Timing emulator for a simple CPU
+// Timing emulator for a simple CPU, driving a CRT
+// The beam sweeps 40 pixels per row, one per cycle, lighting a pixel if the 3-wide sprite centred on X
+// covers it. The instruction set is in cpu.rs.
+// The 40x6 frame is read as text with the built-in font (font.rs) and the text printed. If any glyph is
+// unknown, the frame is printed as pixels instead, with a warning. Past 240 cycles, the beam starts over.
+// --trace FILE writes every cycle to CSV; --debug runs under an interactive debugger (see debugger.rs),
+// taking commands from stdin, so the program must come from a file.
 
 use std::io::{BufRead, BufReader, Error, ErrorKind, Stdin, stdin};
 use std::fs::File;
 use either::Either;
+use clap::Parser;
+
+mod cpu;
+mod debugger;
+mod font;
+
+use cpu::{Peripheral, Registers};
+
+#[derive(Parser)]
+struct Cli {
+	#[arg(long = "debug")]
+	debug: bool,
+	#[arg(long = "trace")]
+	trace: Option<String>,
+	filename: Option<String>
+}
 
 const DEBUG_GRID:bool = false;
 
+const WIDTH:usize = 40;
+const HEIGHT:usize = font::GLYPH_HEIGHT;
+
+// CRT beam, lighting pixels of a row-major frame
+struct Crt {
+	frame:Vec<bool>
+}
+
+impl Peripheral for Crt {
+	fn cycle(&mut self, cycle:i64, regs:&Registers) {
+		let beam = ((cycle-1) as usize) % self.frame.len(); // Cycles count from 1, pixels from 0
+		self.frame[beam] = (((beam%WIDTH) as i64)-regs[0]).abs() <=1;
+	}
+}
+
+fn print_frame(frame:&[bool]) {
+	for row in frame.chunks(WIDTH) {
+		let row:String = row.iter().map(|&x| if x {'#'} else if DEBUG_GRID {'.'} else {' '}).collect();
+		println!("{}", row);
+	}
+}
+
 fn main() -> Result<(), Error> {
+	let (filename, debug, trace) = {
+		let cli = Cli::parse();
+		(cli.filename, cli.debug, cli.trace)
+	};
+	if debug && filename.is_none() {
+		return Err(Error::new(ErrorKind::InvalidInput, "--debug reads commands from stdin, so the program must be in a file"))
+	}
+
     // Load file from command-line argument or (if none) stdin
-	let filename = std::env::args().fuse().nth(1);
 	let input: Either<BufReader<Stdin>, BufReader<File>> = match &filename {
 		None => either::Left(BufReader::new(stdin())),
 		Some(x) => either::Right(BufReader::new(std::fs::File::open(x)?))
 	};
 
-	let lines = input.lines();
-
-	let invalide = || { Error::new(ErrorKind::InvalidInput, "Invalid number argument") };
-	let invalid2 = || { Err(Error::new(ErrorKind::InvalidInput, "Unrecognized command")) };
-
-	let mut reg: i64 = 1;
-	let mut cycle:i64 = 0;
-	let mut advance = |x:i64, reg:&i64| {
-		let mut x = x;
-		while x>0 {
-			{
-				if cycle>1 && cycle%40==0 { println!(""); }
-				print!("{}",
-					if ((cycle%40)-*reg).abs() <=1 {'#'}
-					else { if DEBUG_GRID {'.'} else {' '} });
-			}
-			x -= 1;
-			cycle += 1;
-		}
-	};
+	let program = cpu::parse_program(input.lines())?;
 
-	// Scan file
-	for line in lines {
-		let line = line?;
-		if line.is_empty() {continue}
-		let mut tokens = line.split_whitespace().fuse();
-		let keyword = tokens.next().unwrap();
-		match keyword {
-			"noop" => { advance(1,&reg); }
-			"addx" => {
-				let x = tokens.next().ok_or_else(invalide)?.parse::<i64>().map_err(|_|invalide())?;
-				advance(2,&reg);
-				reg += x;
-			}
-			_ => {
-				return invalid2()
-			}
-		}
-	}
+	let mut session = debugger::Session::new(&program, debug, trace.as_deref())?;
+	let mut crt = Crt { frame:vec![false; WIDTH*HEIGHT] };
+	if debug { debugger::interactive(&mut session, &mut [&mut crt])? } else { session.run(&mut [&mut crt])? }
 
-	println!("");
+	let (text, all_known) = font::read(&crt.frame, WIDTH);
+	if all_known {
+		println!("{}", text);
+	} else {
+		eprintln!("Warning: couldn't read every letter (got \"{}\"), so here's the screen", text);
+		print_frame(&crt.frame);
+	}
 
 	Ok(())
 }
@@ -1,34 +1,103 @@
 // Parses a series of monkey descriptions. Simulates anxiety.
+// Operations may be any arithmetic on old (see expr.rs). Arithmetic is checked, so worry too big for
+// 64 bits is an error; --big works it out exactly with big integers instead.
 
 use std::io::{BufRead, BufReader, Error, ErrorKind, Stdin, stdin};
 use std::fs::File;
 use either::Either;
+use clap::Parser as CliParser;
+use num_bigint::BigUint;
 
-#[derive(Debug)]
-enum Op {
-	Plus, Times
-}
+mod expr;
+
+use expr::{Expr, Op, Worry};
 
-#[derive(Debug)]
-enum Operand {
-	Old,
-	Literal(u64)
+#[derive(CliParser)]
+struct Cli {
+	#[arg(long = "big")]
+	big: bool,
+	filename: Option<String>
 }
 
-struct Monkey {
-	holding:Vec<u64>,
-	operation:(Op, Operand),
+struct Monkey<W> {
+	holding:Vec<W>,
+	operation:Expr,
 	divisible:u64,
 	if_true:usize,
 	if_false:usize,
 	inspections:u64
 }
 
+impl Monkey<u64> {
+	fn exact(self) -> Monkey<BigUint> {
+		Monkey {
+			holding:self.holding.into_iter().map(BigUint::from).collect(), operation:self.operation,
+			divisible:self.divisible, if_true:self.if_true, if_false:self.if_false, inspections:self.inspections
+		}
+	}
+}
+
 const MONKEY_ROUNDS:u64 = 20;
 
+// Each monkey in turn inspects, calms down over and throws everything it holds
+fn round<W:Worry>(monkeys:&mut [Monkey<W>]) -> Result<(), Error> {
+	for monkey_idx in 0..monkeys.len() {
+		let (under, monkey) = monkeys.split_at_mut(monkey_idx);
+		let (monkey, over) = monkey.split_at_mut(1); // Notice monkey not monkeys
+		let monkey = &mut monkey[0];
+
+		/*
+		let mut other_monkey = |other_idx:usize|->&mut Monkey {
+			if other_idx<monkey_idx { &mut under[other_idx] }
+			else if other_idx>monkey_idx { &mut over[other_idx-monkey_idx-1 ] }
+			else { panic!("Impossible error")}
+		};
+		*/
+
+		let inspect_idx = 0;
+		while inspect_idx < monkey.holding.len() {
+			// FIRST increment worry
+			let worry = monkey.operation.eval(&monkey.holding[inspect_idx]).map_err(|e|Error::new(ErrorKind::InvalidInput, e))?;
+			// THEN calm down
+			monkey.holding[inspect_idx] = W::apply(Op::Divide, worry, W::from_u64(3)).map_err(|e|Error::new(ErrorKind::InvalidInput, e))?;
+			// THEN throw
+			let other_monkey_idx = if monkey.holding[inspect_idx].divisible_by(monkey.divisible) {
+				monkey.if_true
+			} else {
+				monkey.if_false
+			};
+			if other_monkey_idx != monkey_idx {
+				let throw = monkey.holding.remove(inspect_idx);
+				let other_monkey = 
+					if other_monkey_idx<monkey_idx { &mut under[other_monkey_idx] }
+					else if other_monkey_idx>monkey_idx { &mut over[other_monkey_idx-monkey_idx-1 ] }
+					else { panic!("Impossible error")};
+				other_monkey.holding.push(throw); // WAIT THIS IS WRONG
+			} else {
+				// There's nothing semantically wrong with this (you could just move it to the end of self)
+				// But it could too easily lead to infinite loops
+				return Err(Error::new(ErrorKind::InvalidInput, "Assuming a monkey cannot throw to itself"))
+				//inspect_idx += 1;
+			}
+			monkey.inspections += 1;
+		}
+	}
+	Ok(())
+}
+
+// The two busiest monkeys' inspections, multiplied
+fn business<W>(monkeys:&mut [Monkey<W>]) -> Result<u64, Error> {
+	monkeys.sort_unstable_by_key(|x|std::cmp::Reverse(x.inspections)); // i64::MAX-
+
+	if monkeys.len() < 2 { return Err(Error::new(ErrorKind::InvalidInput, "Expected at least two monkeys")) }
+	monkeys[0].inspections.checked_mul(monkeys[1].inspections).ok_or_else(||Error::new(ErrorKind::InvalidData, "Monkey business overflowed"))
+}
+
 fn main() -> Result<(), Error> {
+	let cli = Cli::parse();
+
     // Load file from command-line argument or (if none) stdin
-	let filename = std::env::args().fuse().nth(1);
+	let filename = cli.filename;
 	let input: Either<BufReader<Stdin>, BufReader<File>> = match &filename {
 		None => either::Left(BufReader::new(stdin())),
 		Some(x) => either::Right(BufReader::new(std::fs::File::open(x)?))
@@ -37,7 +106,7 @@ fn main() -> Result<(), Error> {
 	// Filter input to remove blank lines.
 	let mut lines = input.lines().filter(|x|match x { Ok(x) => !x.is_empty(), _ => true }).peekable();
 
-	let mut monkeys:Vec<Monkey> = Default::default();
+	let mut monkeys:Vec<Monkey<u64>> = Default::default();
 
 	{
 		use pom::parser::*;
@@ -71,13 +140,6 @@ fn main() -> Result<(), Error> {
 			not_number() * comma_separated_positive() - whitespace()   // a comma-separated list of ints
 		}
 
-		fn ends_with_operation<'a>() -> Parser<'a, u8, (Op, Operand)> {
-			none_of(b"*+").repeat(0..) * (
-				( (sym(b'+').map(|_|Op::Plus) | sym(b'*').map(|_|Op::Times)) - whitespace() ) + 
-				( seq(b"old").map(|_|Operand::Old) | positive().map(Operand::Literal))
-			)
-		}
-
 		let invalide = |s| { Error::new(ErrorKind::InvalidInput, format!("Unrecognized line '{}'", s)) };
 		fn next<I, T:Iterator<Item = Result<I, Error>>>(l:&mut T) -> Result<I, Error> { match (*l).next() { Some(x) => x, None => Err(Error::new(ErrorKind::InvalidInput, "Incomplete monkey")) } }
 
@@ -98,13 +160,14 @@ fn main() -> Result<(), Error> {
 				operation: {
 					let temp = next(&mut lines)?;
 					let temp2 = temp.clone();
-					let temp = ends_with_operation().parse(temp.as_bytes()).map_err(|_|invalide(temp2))?;
+					let temp = expr::ends_with_expression().parse(temp.as_bytes()).map_err(|_|invalide(temp2))?;
 					temp
 				},
 				divisible: {
 					let temp = next(&mut lines)?;
 					let temp2 = temp.clone();
-					let temp = ends_with_positive().parse(temp.as_bytes()).map_err(|_|invalide(temp2))?;
+					let temp = ends_with_positive().parse(temp.as_bytes()).map_err(|_|invalide(temp2.clone()))?;
+					if temp == 0 { return Err(Error::new(ErrorKind::InvalidInput, format!("Can't test divisibility by 0 in '{}'", temp2))) }
 					temp
 				},
 				if_true: as_usize({
@@ -125,73 +188,21 @@ fn main() -> Result<(), Error> {
 			monkeys.push(monkey);
 
 			// If EOF occurs at this known place, break cleanly.
-			if let None = lines.peek() { break }
+			if lines.peek().is_none() { break }
 		}
 	}
 
-	for _ in 0..MONKEY_ROUNDS {
-		for monkey_idx in 0..monkeys.len() {
-			let (mut under, mut monkey) = monkeys.split_at_mut(monkey_idx);
-			let (mut monkey, mut over) = monkey.split_at_mut(1); // Notice monkey not monkeys
-			let mut monkey = &mut monkey[0];
-
-			/*
-			let mut other_monkey = |other_idx:usize|->&mut Monkey {
-				if other_idx<monkey_idx { &mut under[other_idx] }
-				else if other_idx>monkey_idx { &mut over[other_idx-monkey_idx-1 ] }
-				else { panic!("Impossible error")}
-			};
-			*/
-
-			let mut inspect_idx = 0;
-			while inspect_idx < monkey.holding.len() {
-				// FIRST increment worry
-				{
-					let (op, operand) = &monkey.operation;
-					let operand = match operand {
-						Operand::Old => monkey.holding[inspect_idx],
-						Operand::Literal(n) => *n
-					};
-					match op {
-						Op::Plus  => { monkey.holding[inspect_idx] += operand },
-						Op::Times => { monkey.holding[inspect_idx] *= operand }
-					};
-				}
-				// THEN calm down
-				monkey.holding[inspect_idx] /= 3;
-				// THEN throw
-				let other_monkey_idx = if monkey.holding[inspect_idx] % monkey.divisible == 0 {
-					monkey.if_true
-				} else {
-					monkey.if_false
-				};
-				if other_monkey_idx != monkey_idx {
-					let throw = monkey.holding.remove(inspect_idx);
-					let other_monkey = 
-						if other_monkey_idx<monkey_idx { &mut under[other_monkey_idx] }
-						else if other_monkey_idx>monkey_idx { &mut over[other_monkey_idx-monkey_idx-1 ] }
-						else { panic!("Impossible error")};
-					other_monkey.holding.push(throw); // WAIT THIS IS WRONG
-				} else {
-					// There's nothing semantically wrong with this (you could just move it to the end of self)
-					// But it could too easily lead to infinite loops
-					return Err(Error::new(ErrorKind::InvalidInput, "Assuming a monkey cannot throw to itself"))
-					//inspect_idx += 1;
-				}
-				monkey.inspections += 1;
-			}
-		}
-	}
-
-	monkeys.sort_unstable_by_key(|x|std::cmp::Reverse(x.inspections)); // i64::MAX-
-
-	{
-		if monkeys.len() < 2 { return Err(Error::new(ErrorKind::InvalidInput, "Expected at least two monkeys")) }
-		let total = monkeys[0].inspections * monkeys[1].inspections;
+	let total = if cli.big {
+		let mut monkeys:Vec<Monkey<BigUint>> = monkeys.into_iter().map(Monkey::exact).collect();
+		for _ in 0..MONKEY_ROUNDS { round(&mut monkeys)? }
+		business(&mut monkeys)?
+	} else {
+		for _ in 0..MONKEY_ROUNDS { round(&mut monkeys)? }
+		business(&mut monkeys)?
+	};
 
-		// Final score
-		println!("{}", total);
-	}
+	// Final score
+	println!("{}", total);
 
 	Ok(())
 }
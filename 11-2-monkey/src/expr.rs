@@ -0,0 +1,169 @@
+// Arithmetic on worry levels: the right-hand side of "Operation: new = ...".
+// Integers and "old", combined with + - * / % (usual precedence, left to right) and parentheses.
+
+use pom::parser::*;
+use num_bigint::BigUint;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Op {
+	Plus, Minus, Times, Divide, Remainder
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+	Old,
+	Literal(u64),
+	Binary(Box<Expr>, Op, Box<Expr>)
+}
+
+impl Op {
+	fn symbol(&self) -> char {
+		match self { Op::Plus => '+', Op::Minus => '-', Op::Times => '*', Op::Divide => '/', Op::Remainder => '%' }
+	}
+}
+
+impl std::fmt::Display for Expr {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			Expr::Old => write!(f, "old"),
+			Expr::Literal(n) => write!(f, "{}", n),
+			Expr::Binary(left, op, right) => {
+				// Parenthesize every nested operation rather than track precedence
+				let side = |e:&Expr| match e { Expr::Binary(..) => format!("({})", e), _ => e.to_string() };
+				write!(f, "{} {} {}", side(left), op.symbol(), side(right))
+			}
+		}
+	}
+}
+
+// A worry level worked out exactly: u64, which errors rather than overflow, or BigUint, which can't overflow
+pub trait Worry: Sized + Clone + std::fmt::Display {
+	fn from_u64(n:u64) -> Self;
+	fn apply(op:Op, a:Self, b:Self) -> Result<Self, String>;
+	fn divisible_by(&self, n:u64) -> bool;
+}
+
+fn undefined(op:Op, a:&dyn std::fmt::Display, b:&dyn std::fmt::Display) -> String {
+	match op {
+		Op::Minus => format!("Worry went negative in {} - {}", a, b),
+		_ => format!("Division by zero in {} {} {}", a, op.symbol(), b)
+	}
+}
+
+impl Worry for u64 {
+	fn from_u64(n:u64) -> Self { n }
+	fn apply(op:Op, a:u64, b:u64) -> Result<u64, String> {
+		let result = match op {
+			Op::Plus => a.checked_add(b), Op::Minus => a.checked_sub(b), Op::Times => a.checked_mul(b),
+			Op::Divide => a.checked_div(b), Op::Remainder => a.checked_rem(b)
+		};
+		result.ok_or_else(|| match op {
+			Op::Plus | Op::Times => format!("Worry overflowed in {} {} {} (--big works exactly)", a, op.symbol(), b),
+			_ => undefined(op, &a, &b)
+		})
+	}
+	fn divisible_by(&self, n:u64) -> bool { self.is_multiple_of(n) }
+}
+
+impl Worry for BigUint {
+	fn from_u64(n:u64) -> Self { BigUint::from(n) }
+	fn apply(op:Op, a:BigUint, b:BigUint) -> Result<BigUint, String> {
+		let zero = BigUint::default();
+		Ok(match op {
+			Op::Plus => a + b,
+			Op::Times => a * b,
+			Op::Minus if a >= b => a - b,
+			Op::Divide if b != zero => a / b,
+			Op::Remainder if b != zero => a % b,
+			_ => return Err(undefined(op, &a, &b))
+		})
+	}
+	fn divisible_by(&self, n:u64) -> bool { self % n == BigUint::default() }
+}
+
+impl Expr {
+	pub fn eval<W:Worry>(&self, old:&W) -> Result<W, String> {
+		match self {
+			Expr::Old => Ok(old.clone()),
+			Expr::Literal(n) => Ok(W::from_u64(*n)),
+			Expr::Binary(left, op, right) => W::apply(*op, left.eval(old)?, right.eval(old)?)
+		}
+	}
+
+	// The result modulo m, from old modulo m. Only meaningful if reducible() and m is a multiple of remainders().
+	// Can't overflow: everything stays below m, and products are taken in u128.
+	pub fn eval_modulo(&self, old:u64, m:u64) -> u64 {
+		match self {
+			Expr::Old => old % m,
+			Expr::Literal(n) => n % m,
+			Expr::Binary(left, op, right) => {
+				let (a, b) = (left.eval_modulo(old, m) as u128, right.eval_modulo(old, m) as u128);
+				let m = m as u128;
+				(match op {
+					Op::Plus => (a + b) % m,
+					Op::Times => (a * b) % m,
+					Op::Remainder => match **right { Expr::Literal(n) => a % n as u128, _ => unreachable!("Not reducible") },
+					Op::Minus | Op::Divide => unreachable!("Not reducible")
+				}) as u64
+			}
+		}
+	}
+
+	// Whether the result modulo m can be found from old modulo m, for any m that the divisors passed to
+	// remainders() divide. Sums and products can; division can't, nor can % by anything but a constant,
+	// and nor can subtraction, as old modulo m can't tell whether the exact result would go negative.
+	pub fn reducible(&self) -> Result<(), String> {
+		match self {
+			Expr::Old | Expr::Literal(_) => Ok(()),
+			Expr::Binary(_, Op::Divide, _) => Err(format!("'{}' divides, so worry can't be kept modulo the tests' divisors", self)),
+			Expr::Binary(_, Op::Minus, _) => Err(format!("'{}' subtracts, and worry kept modulo the tests' divisors can't tell if it would go negative", self)),
+			Expr::Binary(left, Op::Remainder, right) => match **right {
+				Expr::Literal(n) if n != 0 => left.reducible(),
+				_ => Err(format!("'{}' takes a remainder by something other than a positive number, so worry can't be kept modulo the tests' divisors", self))
+			},
+			Expr::Binary(left, _, right) => { left.reducible()?; right.reducible() }
+		}
+	}
+
+	// The constants this takes remainders by, which the modulus must be a multiple of
+	pub fn remainders(&self, found:&mut Vec<u64>) {
+		if let Expr::Binary(left, op, right) = self {
+			if let (Op::Remainder, Expr::Literal(n)) = (op, &**right) { found.push(*n) }
+			left.remainders(found);
+			right.remainders(found);
+		}
+	}
+}
+
+fn whitespace<'a>() -> Parser<'a, u8, ()> {
+	one_of(b" \t").repeat(0..).discard()
+}
+
+fn number<'a>() -> Parser<'a, u8, u64> {
+	one_of(b"0123456789").repeat(1..).collect().convert(|s|String::from_iter(s.iter().map(|s|*s as char)).parse::<u64>())
+}
+
+fn atom<'a>() -> Parser<'a, u8, Expr> {
+	( seq(b"old").map(|_|Expr::Old)
+	| number().map(Expr::Literal)
+	| (sym(b'(') * whitespace() * call(sum) - sym(b')')) ) - whitespace()
+}
+
+// Left-associative run of operands at one precedence level
+fn chain<'a>(operand:fn() -> Parser<'a, u8, Expr>, ops:&'static [u8]) -> Parser<'a, u8, Expr> {
+	let op = one_of(ops).map(|c| match c {
+		b'+' => Op::Plus, b'-' => Op::Minus, b'*' => Op::Times, b'/' => Op::Divide, _ => Op::Remainder
+	}) - whitespace();
+	(operand() + (op + operand()).repeat(0..)).map(|(first, rest)| {
+		rest.into_iter().fold(first, |left, (op, right)| Expr::Binary(Box::new(left), op, Box::new(right)))
+	})
+}
+
+fn product<'a>() -> Parser<'a, u8, Expr> { chain(atom, b"*/%") }
+
+fn sum<'a>() -> Parser<'a, u8, Expr> { chain(product, b"+-") }
+
+// Matches any line ending with "= EXPR"
+pub fn ends_with_expression<'a>() -> Parser<'a, u8, Expr> {
+	none_of(b"=").repeat(0..) * sym(b'=') * whitespace() * sum() - end()
+}
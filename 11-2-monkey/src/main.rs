@@ -1,35 +1,155 @@
 // Parses a series of monkey descriptions. Simulates anxiety.
 // Allows absurd amounts of anxiety.
+// Operations may be any arithmetic on old (see expr.rs), as long as it can be kept modulo the tests' divisors.
+// --rounds sets how many rounds to play. Worry is kept modulo the product of the divisors, working in u128,
+// so it can't overflow; a product of divisors or a final score too big for 64 bits is an error instead.
+// --big works out every worry level exactly with big integers, which also allows subtraction and division,
+// but gets slow fast. --check N plays N rounds both ways and confirms the kept-modulo worry matches the exact.
 
 use std::io::{BufRead, BufReader, Error, ErrorKind, Stdin, stdin};
 use std::fs::File;
 use either::Either;
+use clap::Parser as CliParser;
+use num_bigint::BigUint;
 
-#[derive(Debug)]
-enum Op {
-	Plus, Times
-}
+mod expr;
+
+use expr::{Expr, Worry};
 
-#[derive(Debug)]
-enum Operand {
-	Old,
-	Literal(u64)
+#[derive(CliParser)]
+struct Cli {
+	#[arg(short = 'r', long = "rounds", default_value_t = MONKEY_ROUNDS)]
+	rounds: u64,
+	#[arg(long = "big")]
+	big: bool,
+	#[arg(long = "check")]
+	check: Option<u64>,
+	filename: Option<String>
 }
 
-struct Monkey {
-	holding:Vec<u64>,
-	operation:(Op, Operand),
+#[derive(Clone)]
+struct Monkey<W> {
+	holding:Vec<W>,
+	operation:Expr,
 	divisible:u64,
 	if_true:usize,
 	if_false:usize,
 	inspections:u64
 }
 
+impl Monkey<u64> {
+	fn exact(&self) -> Monkey<BigUint> {
+		Monkey {
+			holding:self.holding.iter().map(|&x|BigUint::from(x)).collect(), operation:self.operation.clone(),
+			divisible:self.divisible, if_true:self.if_true, if_false:self.if_false, inspections:self.inspections
+		}
+	}
+}
+
 const MONKEY_ROUNDS:u64 = 10000;
 
+// Everything the monkeys test for or take remainders by divides this, so worry can be kept modulo it
+fn modulus<W>(monkeys:&[Monkey<W>]) -> Result<u64, Error> {
+	let mut modulus:u64 = 1;
+	for monkey in monkeys {
+		monkey.operation.reducible().map_err(|e|Error::new(ErrorKind::InvalidInput, format!("{} (--big works exactly)", e)))?;
+		let mut divisors = vec![monkey.divisible];
+		monkey.operation.remainders(&mut divisors);
+		for n in divisors {
+			if !modulus.is_multiple_of(n) {
+				modulus = modulus.checked_mul(n).ok_or_else(||Error::new(ErrorKind::InvalidInput, "Product of divisors overflowed (--big works exactly)"))?
+			}
+		}
+	}
+	Ok(modulus)
+}
+
+// Each monkey in turn inspects and throws everything it holds. inspect gives an item's new worry.
+fn round<W:Worry>(monkeys:&mut [Monkey<W>], inspect:&dyn Fn(&Expr, &W) -> Result<W, String>) -> Result<(), Error> {
+	for monkey_idx in 0..monkeys.len() {
+		let (under, monkey) = monkeys.split_at_mut(monkey_idx);
+		let (monkey, over) = monkey.split_at_mut(1); // Notice monkey not monkeys
+		let monkey = &mut monkey[0];
+
+		/*
+		let mut other_monkey = |other_idx:usize|->&mut Monkey {
+			if other_idx<monkey_idx { &mut under[other_idx] }
+			else if other_idx>monkey_idx { &mut over[other_idx-monkey_idx-1 ] }
+			else { panic!("Impossible error")}
+		};
+		*/
+
+		let inspect_idx = 0;
+		while inspect_idx < monkey.holding.len() {
+			// FIRST increment worry
+			monkey.holding[inspect_idx] = inspect(&monkey.operation, &monkey.holding[inspect_idx])
+				.map_err(|e|Error::new(ErrorKind::InvalidInput, e))?;
+			// THEN throw
+			let other_monkey_idx = if monkey.holding[inspect_idx].divisible_by(monkey.divisible) {
+				monkey.if_true
+			} else {
+				monkey.if_false
+			};
+			if other_monkey_idx != monkey_idx {
+				let throw = monkey.holding.remove(inspect_idx);
+				let other_monkey = 
+					if other_monkey_idx<monkey_idx { &mut under[other_monkey_idx] }
+					else if other_monkey_idx>monkey_idx { &mut over[other_monkey_idx-monkey_idx-1 ] }
+					else { panic!("Impossible error")};
+				other_monkey.holding.push(throw); // WAIT THIS IS WRONG
+			} else {
+				// There's nothing semantically wrong with this (you could just move it to the end of self)
+				// But it could too easily lead to infinite loops
+				return Err(Error::new(ErrorKind::InvalidInput, "Assuming a monkey cannot throw to itself"))
+				//inspect_idx += 1;
+			}
+			monkey.inspections += 1;
+		}
+	}
+	Ok(())
+}
+
+// Play rounds exactly and modulo, and fail at the first round where they disagree
+fn self_check(monkeys:&[Monkey<u64>], rounds:u64) -> Result<(), Error> {
+	let modulus = modulus(monkeys)?;
+	let mut reduced = monkeys.to_vec();
+	let mut exact:Vec<Monkey<BigUint>> = monkeys.iter().map(Monkey::exact).collect();
+	for round_idx in 1..=rounds {
+		round(&mut reduced, &|e, &x| Ok(e.eval_modulo(x, modulus)))?;
+		round(&mut exact, &|e, x| e.eval(x))?;
+		for (monkey_idx, (r, e)) in reduced.iter().zip(&exact).enumerate() {
+			let mismatch = |what:String| Error::new(ErrorKind::InvalidData,
+				format!("Self-check failed in round {}: monkey {} {}", round_idx, monkey_idx, what));
+			if r.inspections != e.inspections {
+				return Err(mismatch(format!("inspected {} items exactly but {} modulo {}", e.inspections, r.inspections, modulus)))
+			}
+			if r.holding.len() != e.holding.len() {
+				return Err(mismatch(format!("holds {} items exactly but {} modulo {}", e.holding.len(), r.holding.len(), modulus)))
+			}
+			for (&x, big) in r.holding.iter().zip(&e.holding) {
+				if BigUint::from(x) != big % modulus {
+					return Err(mismatch(format!("holds worry {} exactly but {} modulo {}", big, x, modulus)))
+				}
+			}
+		}
+	}
+	println!("Self-check passed: {} round(s) agree exactly and modulo {}", rounds, modulus);
+	Ok(())
+}
+
+// The two busiest monkeys' inspections, multiplied
+fn business<W>(monkeys:&mut [Monkey<W>]) -> Result<u64, Error> {
+	monkeys.sort_unstable_by_key(|x|std::cmp::Reverse(x.inspections)); // i64::MAX-
+
+	if monkeys.len() < 2 { return Err(Error::new(ErrorKind::InvalidInput, "Expected at least two monkeys")) }
+	monkeys[0].inspections.checked_mul(monkeys[1].inspections).ok_or_else(||Error::new(ErrorKind::InvalidData, "Monkey business overflowed"))
+}
+
 fn main() -> Result<(), Error> {
+	let cli = Cli::parse();
+
     // Load file from command-line argument or (if none) stdin
-	let filename = std::env::args().fuse().nth(1);
+	let filename = cli.filename;
 	let input: Either<BufReader<Stdin>, BufReader<File>> = match &filename {
 		None => either::Left(BufReader::new(stdin())),
 		Some(x) => either::Right(BufReader::new(std::fs::File::open(x)?))
@@ -38,8 +158,7 @@ fn main() -> Result<(), Error> {
 	// Filter input to remove blank lines.
 	let mut lines = input.lines().filter(|x|match x { Ok(x) => !x.is_empty(), _ => true }).peekable();
 
-	let mut monkeys:Vec<Monkey> = Default::default();
-	let mut modulus:u64 = 1;
+	let mut monkeys:Vec<Monkey<u64>> = Default::default();
 
 	{
 		use pom::parser::*;
@@ -73,13 +192,6 @@ fn main() -> Result<(), Error> {
 			not_number() * comma_separated_positive() - whitespace()   // a comma-separated list of ints
 		}
 
-		fn ends_with_operation<'a>() -> Parser<'a, u8, (Op, Operand)> {
-			none_of(b"*+").repeat(0..) * (
-				( (sym(b'+').map(|_|Op::Plus) | sym(b'*').map(|_|Op::Times)) - whitespace() ) + 
-				( seq(b"old").map(|_|Operand::Old) | positive().map(Operand::Literal))
-			)
-		}
-
 		let invalide = |s| { Error::new(ErrorKind::InvalidInput, format!("Unrecognized line '{}'", s)) };
 		fn next<I, T:Iterator<Item = Result<I, Error>>>(l:&mut T) -> Result<I, Error> { match (*l).next() { Some(x) => x, None => Err(Error::new(ErrorKind::InvalidInput, "Incomplete monkey")) } }
 
@@ -100,14 +212,14 @@ fn main() -> Result<(), Error> {
 				operation: {
 					let temp = next(&mut lines)?;
 					let temp2 = temp.clone();
-					let temp = ends_with_operation().parse(temp.as_bytes()).map_err(|_|invalide(temp2))?;
+					let temp = expr::ends_with_expression().parse(temp.as_bytes()).map_err(|_|invalide(temp2))?;
 					temp
 				},
 				divisible: {
 					let temp = next(&mut lines)?;
 					let temp2 = temp.clone();
-					let temp = ends_with_positive().parse(temp.as_bytes()).map_err(|_|invalide(temp2))?;
-					if modulus % temp != 0 { modulus *= temp };
+					let temp = ends_with_positive().parse(temp.as_bytes()).map_err(|_|invalide(temp2.clone()))?;
+					if temp == 0 { return Err(Error::new(ErrorKind::InvalidInput, format!("Can't test divisibility by 0 in '{}'", temp2))) }
 					temp
 				},
 				if_true: as_usize({
@@ -128,73 +240,24 @@ fn main() -> Result<(), Error> {
 			monkeys.push(monkey);
 
 			// If EOF occurs at this known place, break cleanly.
-			if let None = lines.peek() { break }
-		}
-	}
-
-	for _ in 0..MONKEY_ROUNDS {
-		for monkey_idx in 0..monkeys.len() {
-			let (mut under, mut monkey) = monkeys.split_at_mut(monkey_idx);
-			let (mut monkey, mut over) = monkey.split_at_mut(1); // Notice monkey not monkeys
-			let mut monkey = &mut monkey[0];
-
-			/*
-			let mut other_monkey = |other_idx:usize|->&mut Monkey {
-				if other_idx<monkey_idx { &mut under[other_idx] }
-				else if other_idx>monkey_idx { &mut over[other_idx-monkey_idx-1 ] }
-				else { panic!("Impossible error")}
-			};
-			*/
-
-			let mut inspect_idx = 0;
-			while inspect_idx < monkey.holding.len() {
-				// FIRST increment worry
-				{
-					let (op, operand) = &monkey.operation;
-					let operand = match operand {
-						Operand::Old => monkey.holding[inspect_idx],
-						Operand::Literal(n) => *n
-					};
-					//println!("{} {:?} {}", monkey.holding[inspect_idx], op, operand); // In case worry overflows...
-					match op {
-						Op::Plus  => { monkey.holding[inspect_idx] += operand },
-						Op::Times => { monkey.holding[inspect_idx] *= operand }
-					};
-					monkey.holding[inspect_idx] %= modulus;
-				}
-				// THEN throw
-				let other_monkey_idx = if monkey.holding[inspect_idx] % monkey.divisible == 0 {
-					monkey.if_true
-				} else {
-					monkey.if_false
-				};
-				if other_monkey_idx != monkey_idx {
-					let throw = monkey.holding.remove(inspect_idx);
-					let other_monkey = 
-						if other_monkey_idx<monkey_idx { &mut under[other_monkey_idx] }
-						else if other_monkey_idx>monkey_idx { &mut over[other_monkey_idx-monkey_idx-1 ] }
-						else { panic!("Impossible error")};
-					other_monkey.holding.push(throw); // WAIT THIS IS WRONG
-				} else {
-					// There's nothing semantically wrong with this (you could just move it to the end of self)
-					// But it could too easily lead to infinite loops
-					return Err(Error::new(ErrorKind::InvalidInput, "Assuming a monkey cannot throw to itself"))
-					//inspect_idx += 1;
-				}
-				monkey.inspections += 1;
-			}
+			if lines.peek().is_none() { break }
 		}
 	}
 
-	monkeys.sort_unstable_by_key(|x|std::cmp::Reverse(x.inspections)); // i64::MAX-
+	if let Some(rounds) = cli.check { self_check(&monkeys, rounds)? }
 
-	{
-		if monkeys.len() < 2 { return Err(Error::new(ErrorKind::InvalidInput, "Expected at least two monkeys")) }
-		let total = monkeys[0].inspections * monkeys[1].inspections;
+	let total = if cli.big {
+		let mut monkeys:Vec<Monkey<BigUint>> = monkeys.iter().map(Monkey::exact).collect();
+		for _ in 0..cli.rounds { round(&mut monkeys, &|e, x| e.eval(x))? }
+		business(&mut monkeys)?
+	} else {
+		let modulus = modulus(&monkeys)?;
+		for _ in 0..cli.rounds { round(&mut monkeys, &|e, &x| Ok(e.eval_modulo(x, modulus)))? }
+		business(&mut monkeys)?
+	};
 
-		// Final score
-		println!("{}", total);
-	}
+	// Final score
+	println!("{}", total);
 
 	Ok(())
 }